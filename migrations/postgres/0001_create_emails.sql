@@ -0,0 +1,8 @@
+-- The table as created before migrations were added. Databases from that time already have it and are upgraded by the
+-- migrations that follow.
+CREATE TABLE IF NOT EXISTS emails (
+    id          SERIAL PRIMARY KEY,
+    address     VARCHAR NOT NULL,
+    domain      VARCHAR NOT NULL,
+    timestamp   TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Rows imported before messages were keyed get a placeholder key, as the messages they came from were not stored.
+ALTER TABLE emails ADD COLUMN message_key VARCHAR;
+UPDATE emails SET message_key = 'legacy:' || id;
+ALTER TABLE emails
+    ALTER COLUMN message_key SET NOT NULL,
+    ADD CONSTRAINT emails_message_key_key UNIQUE (message_key);
+-- Those rows were also inserted with ids counted from 0 without advancing the sequence, which new rows would reuse.
+SELECT setval(pg_get_serial_sequence('emails', 'id'), max(id) + 1, false) FROM emails;
@@ -0,0 +1,8 @@
+ALTER TABLE emails
+    ADD COLUMN message_id   VARCHAR,
+    ADD COLUMN subject      VARCHAR,
+    ADD COLUMN to_addresses VARCHAR,
+    ADD COLUMN cc_addresses VARCHAR,
+    ADD COLUMN reply_to     VARCHAR,
+    ADD COLUMN return_path  VARCHAR,
+    ADD COLUMN list_id      VARCHAR;
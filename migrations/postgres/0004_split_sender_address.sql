@@ -0,0 +1,11 @@
+ALTER TABLE emails
+    ADD COLUMN display_name VARCHAR,
+    ADD COLUMN local_part   VARCHAR NOT NULL DEFAULT '';
+
+UPDATE emails SET
+    display_name = NULLIF(btrim(substring(address FROM '^(.*)<'), ' "'), ''),
+    address = coalesce(substring(address FROM '<([^<>]+)>'), address);
+
+UPDATE emails SET local_part = split_part(address, '@', 1);
+
+ALTER TABLE emails ALTER COLUMN local_part DROP DEFAULT;
@@ -0,0 +1,2 @@
+-- Rows imported before this migration keep a NULL registrable_domain; reports fall back to domain for them.
+ALTER TABLE emails ADD COLUMN registrable_domain VARCHAR;
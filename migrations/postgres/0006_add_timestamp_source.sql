@@ -0,0 +1,2 @@
+ALTER TABLE emails ADD COLUMN timestamp_source VARCHAR NOT NULL DEFAULT 'mbox_separator';
+ALTER TABLE emails ALTER COLUMN timestamp_source DROP DEFAULT;
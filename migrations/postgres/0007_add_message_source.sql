@@ -0,0 +1,4 @@
+-- Rows imported before this migration have no recorded source file or folder.
+ALTER TABLE emails ADD COLUMN source_file VARCHAR;
+ALTER TABLE emails ADD COLUMN folder VARCHAR;
+CREATE INDEX emails_folder_idx ON emails (folder);
@@ -0,0 +1,5 @@
+ALTER TABLE emails ALTER COLUMN id TYPE BIGINT;
+ALTER SEQUENCE emails_id_seq AS BIGINT;
+-- Rows imported before this migration have no content hash, as their raw bytes were never stored.
+ALTER TABLE emails ADD COLUMN content_hash VARCHAR;
+CREATE INDEX emails_content_hash_idx ON emails (content_hash);
@@ -0,0 +1,12 @@
+-- Rows imported before this migration have no body hash and no recorded locations; importing their mailbox again
+-- records the locations.
+ALTER TABLE emails ADD COLUMN body_hash VARCHAR;
+CREATE TABLE message_locations (
+    message_key VARCHAR NOT NULL REFERENCES emails (message_key),
+    source_file VARCHAR NOT NULL,
+    folder      VARCHAR NOT NULL,
+    -- Number of earlier copies of the message in the same file, which unlike a byte offset stays the same when other
+    -- messages are removed from the file
+    occurrence  INTEGER NOT NULL,
+    PRIMARY KEY (message_key, source_file, occurrence)
+);
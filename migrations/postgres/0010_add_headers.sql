@@ -0,0 +1,2 @@
+-- Rows imported before this migration have no stored headers.
+ALTER TABLE emails ADD COLUMN headers JSONB;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS emails (
+    id          INTEGER PRIMARY KEY,
+    address     TEXT NOT NULL,
+    domain      TEXT NOT NULL,
+    timestamp   TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE emails ADD COLUMN message_key TEXT NOT NULL DEFAULT '';
+UPDATE emails SET message_key = 'legacy:' || id;
+CREATE UNIQUE INDEX emails_message_key_idx ON emails (message_key);
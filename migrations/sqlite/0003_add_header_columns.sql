@@ -0,0 +1,7 @@
+ALTER TABLE emails ADD COLUMN message_id   TEXT;
+ALTER TABLE emails ADD COLUMN subject      TEXT;
+ALTER TABLE emails ADD COLUMN to_addresses TEXT;
+ALTER TABLE emails ADD COLUMN cc_addresses TEXT;
+ALTER TABLE emails ADD COLUMN reply_to     TEXT;
+ALTER TABLE emails ADD COLUMN return_path  TEXT;
+ALTER TABLE emails ADD COLUMN list_id      TEXT;
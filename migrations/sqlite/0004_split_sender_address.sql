@@ -0,0 +1,2 @@
+ALTER TABLE emails ADD COLUMN display_name TEXT;
+ALTER TABLE emails ADD COLUMN local_part   TEXT NOT NULL DEFAULT '';
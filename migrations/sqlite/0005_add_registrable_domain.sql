@@ -0,0 +1 @@
+ALTER TABLE emails ADD COLUMN registrable_domain TEXT;
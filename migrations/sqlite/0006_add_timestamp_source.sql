@@ -0,0 +1 @@
+ALTER TABLE emails ADD COLUMN timestamp_source TEXT NOT NULL DEFAULT 'mbox_separator';
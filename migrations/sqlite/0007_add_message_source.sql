@@ -0,0 +1,3 @@
+ALTER TABLE emails ADD COLUMN source_file TEXT;
+ALTER TABLE emails ADD COLUMN folder TEXT;
+CREATE INDEX emails_folder_idx ON emails (folder);
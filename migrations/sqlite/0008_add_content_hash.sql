@@ -0,0 +1,3 @@
+-- INTEGER PRIMARY KEY is already a 64-bit rowid in SQLite, so only the hash column is needed.
+ALTER TABLE emails ADD COLUMN content_hash TEXT;
+CREATE INDEX emails_content_hash_idx ON emails (content_hash);
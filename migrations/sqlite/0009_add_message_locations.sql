@@ -0,0 +1,8 @@
+ALTER TABLE emails ADD COLUMN body_hash TEXT;
+CREATE TABLE message_locations (
+    message_key TEXT NOT NULL REFERENCES emails (message_key),
+    source_file TEXT NOT NULL,
+    folder      TEXT NOT NULL,
+    occurrence  INTEGER NOT NULL,
+    PRIMARY KEY (message_key, source_file, occurrence)
+);
@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use clap::{builder::RangedU64ValueParser, ArgAction, Args, Parser, Subcommand, ValueEnum};
+
+use serde::Deserialize;
+
+use crate::{config::Config, export::OutputFormat, input::MboxFormat, logging::LogFormat, storage::{SslMode, TlsOptions}};
+
+const DEFAULT_DATABASE_URL: &str = "inbox-parser.sqlite3";
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+const MAX_BATCH_SIZE: u64 = 1_000_000;
+const DEFAULT_REPORT_LIMIT: i64 = 20;
+
+#[derive(Parser)]
+#[command(version, about = "Email parsing utility to identify who is spamming your inbox")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Config file to read settings from [default: inbox-parser.toml if it exists]
+    #[arg(long, value_name = "PATH", env = "INBOX_PARSER_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Log more detail; repeat for even more
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Log only warnings, or only errors when repeated
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "verbose")]
+    pub quiet: u8,
+
+    /// Format of the log written to stderr
+    #[arg(long, value_enum, global = true, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Parse a mailbox and load its messages into the database
+    Import(ImportArgs),
+    /// Rank senders and domains by message volume
+    Report(ReportArgs),
+    /// Manage the database schema
+    Migrate(MigrateArgs)
+}
+
+#[derive(Args)]
+pub struct ImportArgs {
+    /// Mbox files, Maildir directories, or directories to search recursively for both; `-` reads an mbox from stdin
+    #[arg(value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
+
+    /// Another mailbox or directory to import; may be repeated
+    #[arg(short, long, value_name = "PATH")]
+    pub input: Vec<PathBuf>,
+
+    /// Mbox dialect of the input, which decides how messages are split
+    #[arg(long, value_enum, default_value_t = MboxFormat::Auto)]
+    pub mbox_format: MboxFormat,
+
+    /// Write the parsed messages to this file instead of the database
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Format of the output file; inferred from its extension when omitted
+    #[arg(long, value_enum, requires = "output")]
+    pub output_format: Option<OutputFormat>,
+
+    /// Write details of every message that failed to import to this file as JSON
+    #[arg(long, value_name = "PATH")]
+    pub errors_json: Option<PathBuf>,
+
+    /// Number of messages written to the database per transaction [default: 1000]
+    #[arg(long, value_name = "COUNT", value_parser = RangedU64ValueParser::<usize>::new().range(1..=MAX_BATCH_SIZE))]
+    pub batch_size: Option<usize>,
+
+    /// Number of threads parsing messages; 0 uses one per CPU [default: 0]
+    #[arg(short, long, value_name = "COUNT")]
+    pub jobs: Option<usize>,
+
+    /// Import every message in a single transaction, or nothing if any message fails
+    #[arg(long)]
+    pub atomic: bool,
+
+    #[command(flatten)]
+    pub database: DatabaseArgs
+}
+
+impl ImportArgs {
+    /// The mailboxes given on the command line, or those listed in the config file if there are none.
+    pub fn inputs(&self, config: &Config) -> Vec<PathBuf> {
+        let inputs = self.paths.iter().chain(&self.input).cloned().collect::<Vec<_>>();
+        if inputs.is_empty() { config.import.inputs.clone() } else { inputs }
+    }
+
+    pub fn batch_size(&self, config: &Config) -> usize {
+        self.batch_size.or(config.import.batch_size).unwrap_or(DEFAULT_BATCH_SIZE)
+    }
+
+    pub fn jobs(&self, config: &Config) -> usize {
+        self.jobs.or(config.import.jobs).unwrap_or(0)
+    }
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    /// Which ranking to print [default: all]
+    #[arg(long, value_enum)]
+    pub by: Option<ReportKind>,
+
+    /// Number of rows to print per ranking [default: 20]
+    #[arg(short = 'n', long, value_parser = clap::value_parser!(i64).range(0..))]
+    pub limit: Option<i64>,
+
+    /// Only count messages imported from this folder
+    #[arg(long)]
+    pub folder: Option<String>,
+
+    #[command(flatten)]
+    pub database: DatabaseArgs
+}
+
+impl ReportArgs {
+    pub fn by(&self, config: &Config) -> ReportKind {
+        self.by.or(config.report.by).unwrap_or(ReportKind::All)
+    }
+
+    pub fn limit(&self, config: &Config) -> i64 {
+        self.limit.or(config.report.limit.map(i64::from)).unwrap_or(DEFAULT_REPORT_LIMIT)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportKind {
+    Senders,
+    Domains,
+    All
+}
+
+#[derive(Args)]
+pub struct MigrateArgs {
+    #[command(subcommand)]
+    pub command: MigrateCommand,
+
+    #[command(flatten)]
+    pub database: DatabaseArgs
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Apply all pending migrations
+    Up,
+    /// List migrations and whether they have been applied
+    Status
+}
+
+#[derive(Args)]
+pub struct DatabaseArgs {
+    /// A postgres:// connection URL, or the path of a SQLite database file [default: inbox-parser.sqlite3]
+    #[arg(long, env = "DATABASE_URL", hide_env_values = true)]
+    pub database_url: Option<String>,
+
+    /// Whether to connect to Postgres over TLS and how to verify the server [default: prefer]
+    #[arg(long, value_enum, env = "PGSSLMODE")]
+    pub sslmode: Option<SslMode>,
+
+    /// PEM file with the CA certificate that signed the Postgres server's certificate
+    #[arg(long, value_name = "PATH", env = "PGSSLROOTCERT")]
+    pub ssl_root_cert: Option<PathBuf>
+}
+
+impl DatabaseArgs {
+    pub fn url(&self, config: &Config) -> String {
+        self.database_url.clone()
+            .or_else(|| config.database_url.clone())
+            .unwrap_or_else(|| DEFAULT_DATABASE_URL.to_string())
+    }
+
+    pub fn tls(&self, config: &Config) -> TlsOptions {
+        TlsOptions {
+            ssl_mode: self.sslmode.or(config.ssl_mode),
+            root_cert: self.ssl_root_cert.clone().or_else(|| config.ssl_root_cert.clone())
+        }
+    }
+}
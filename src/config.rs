@@ -0,0 +1,112 @@
+use std::{collections::HashMap, error::Error, fmt::Display, fs, io, path::{Path, PathBuf}};
+
+use glob::{Pattern, PatternError};
+use serde::Deserialize;
+
+use crate::{cli::ReportKind, input::STDIN_PATH, storage::SslMode};
+
+/// Config file read from the working directory when `--config` is not given
+pub const DEFAULT_CONFIG_PATH: &str = "inbox-parser.toml";
+
+/// Settings from `inbox-parser.toml`. Command line flags and environment variables take precedence over them.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database_url: Option<String>,
+    pub ssl_mode: Option<SslMode>,
+    /// Relative to the config file
+    pub ssl_root_cert: Option<PathBuf>,
+    pub import: ImportConfig,
+    pub report: ReportConfig,
+    /// Domains reported under another domain, e.g. `"googlemail.com" = "gmail.com"`
+    pub domain_aliases: HashMap<String, String>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImportConfig {
+    /// Mailboxes imported when none are given on the command line, relative to the config file
+    pub inputs: Vec<PathBuf>,
+    /// Glob patterns for files and directories to skip while searching directories for mailboxes
+    pub exclude: Vec<String>,
+    #[serde(skip)]
+    pub exclude_patterns: Vec<Pattern>,
+    pub batch_size: Option<usize>,
+    pub jobs: Option<usize>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportConfig {
+    pub by: Option<ReportKind>,
+    pub limit: Option<u32>,
+    /// Sender addresses left out of reports, such as your own
+    pub exclude_senders: Vec<String>,
+    /// Domains whose senders are left out of reports
+    pub exclude_domains: Vec<String>
+}
+
+impl Config {
+    /// Loads the given config file, or the default one if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_PATH).is_file() => Path::new(DEFAULT_CONFIG_PATH),
+            None => return Ok(Config::default())
+        };
+        let contents = fs::read_to_string(path).map_err(|error| ConfigError::Io(path.to_path_buf(), error))?;
+        let mut config: Config = toml::from_str(&contents).map_err(|error| ConfigError::Toml(path.to_path_buf(), error))?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+        config.database_url = config.database_url.map(|database_url| resolve_database_url(directory, database_url));
+        config.import.inputs = config.import.inputs.iter()
+            .map(|input| if input.as_os_str() == STDIN_PATH { input.clone() } else { directory.join(input) })
+            .collect();
+        config.ssl_root_cert = config.ssl_root_cert.map(|root_cert| directory.join(root_cert));
+        config.import.exclude_patterns = config.import.exclude.iter()
+            .map(|pattern| Pattern::new(pattern).map_err(|error| ConfigError::Pattern(pattern.clone(), error)))
+            .collect::<Result<_, _>>()?;
+        Ok(config)
+    }
+}
+
+/// Resolves the path of a SQLite database relative to the config file's directory, leaving Postgres URLs and
+/// in-memory databases unchanged.
+fn resolve_database_url(directory: &Path, database_url: String) -> String {
+    if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        return database_url;
+    }
+    let (scheme, path) = ["sqlite://", "sqlite:"].iter()
+        .find_map(|scheme| Some((*scheme, database_url.strip_prefix(scheme)?)))
+        .unwrap_or(("", &database_url));
+    if path == ":memory:" || Path::new(path).is_absolute() {
+        return database_url;
+    }
+    format!("{}{}", scheme, directory.join(path).display())
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Toml(PathBuf, toml::de::Error),
+    Pattern(String, PatternError)
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, error) => write!(f, "Failed to read {}: {}", path.display(), error),
+            ConfigError::Toml(path, error) => write!(f, "Invalid config file {}: {}", path.display(), error),
+            ConfigError::Pattern(pattern, error) => write!(f, "Invalid exclude pattern {:?}: {}", pattern, error)
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(_, error) => Some(error),
+            ConfigError::Toml(_, error) => Some(error),
+            ConfigError::Pattern(_, error) => Some(error)
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use mailparse::{addrparse_header, dateparse, parse_headers, MailAddr, MailHeader, MailHeaderMap, SingleInfo};
+use regex::Regex;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{error::MessageError, input::{Envelope, RawMessage}};
+
+lazy_static! {
+    static ref RE: Regex = Regex::new(r"[^<>@\s]+@[^<>@\s]+").unwrap();
+}
+
+const SEPARATOR_DATE_FORMATS: &[&str] = &[
+    "%a %b %d %T %z %Y",
+    "%a %b %d %T %Y %z"
+];
+
+const SEPARATOR_ASCTIME_FORMATS: &[&str] = &[
+    "%a %b %e %T %Y",
+    "%a %b %e %H:%M %Y"
+];
+
+/// Zone abbreviations found in place of a numeric offset in separator dates, such as `Mon Jan  2 10:00:00 EST 2023`,
+/// with their offsets from UTC in hours. chrono cannot parse them, and only the ones defined by RFC 822 are unambiguous.
+const ZONE_ABBREVIATIONS: &[(&str, i32)] = &[
+    ("UT", 0), ("UTC", 0), ("GMT", 0), ("Z", 0),
+    ("EST", -5), ("EDT", -4), ("CST", -6), ("CDT", -5), ("MST", -7), ("MDT", -6), ("PST", -8), ("PDT", -7)
+];
+
+#[derive(Clone, Copy)]
+pub enum TimestampSource {
+    MboxSeparator,
+    MaildirFilename,
+    DateHeader
+}
+
+impl TimestampSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimestampSource::MboxSeparator => "mbox_separator",
+            TimestampSource::MaildirFilename => "maildir_filename",
+            TimestampSource::DateHeader => "date_header"
+        }
+    }
+}
+
+pub struct EmailEntry {
+    pub message_key: String,
+    /// Hex SHA-256 of the raw message bytes, which identifies a message independently of where it was read from
+    pub content_hash: String,
+    /// Hex SHA-256 of the normalized body, which stays the same when a copy of the message gains or loses headers
+    pub body_hash: String,
+    pub message_id: Option<String>,
+    pub address: String,
+    pub display_name: Option<String>,
+    pub local_part: String,
+    pub domain: String,
+    pub registrable_domain: String,
+    pub subject: Option<String>,
+    pub to: Option<String>,
+    pub cc: Option<String>,
+    pub reply_to: Option<String>,
+    pub return_path: Option<String>,
+    pub list_id: Option<String>,
+    pub message_timestamp: DateTime<FixedOffset>,
+    pub timestamp_source: TimestampSource,
+    pub source_file: String,
+    pub folder: String,
+    /// Number of earlier messages with the same key in the same file, counted by the import
+    pub occurrence: usize,
+    /// Every header as a `[name, value]` pair, in order and including repeated headers
+    pub headers: Value
+}
+
+impl EmailEntry {
+    pub fn parse(message: &RawMessage) -> Result<EmailEntry, MessageError> {
+        let (headers, body_offset) = parse_headers(&message.data)?;
+        let message_id = header_value(&headers, "Message-ID");
+        let content_hash = format!("{:x}", Sha256::digest(&message.data));
+        let body_hash = normalized_body_hash(&message.data[body_offset..]);
+        let message_key = parse_message_key(&body_hash, message_id.as_deref());
+        let senders = parse_senders(&headers);
+        if senders.len() > 1 {
+            debug!(
+                source_file = %message.source.file,
+                offset = message.offset,
+                ignored = ?senders[1..].iter().map(|sender| &sender.addr).collect::<Vec<_>>(),
+                "Keeping only the first of several From addresses"
+            );
+        }
+        let (display_name, address) = match senders.into_iter().next() {
+            Some(sender) => (sender.display_name, sender.addr),
+            None => (None, parse_fallback_address(message, &headers))
+        };
+        let (local_part, domain) = match address.rsplit_once('@') {
+            Some((local_part, domain)) => (local_part.to_string(), domain.to_lowercase()),
+            None => (address.clone(), String::new())
+        };
+        let registrable_domain = registrable_domain(&domain);
+        let (message_timestamp, timestamp_source) = parse_message_timestamp(message, &headers)?;
+        Ok(EmailEntry {
+            message_key,
+            content_hash,
+            body_hash,
+            message_id,
+            address,
+            display_name,
+            local_part,
+            domain,
+            registrable_domain,
+            subject: header_value(&headers, "Subject"),
+            to: header_value(&headers, "To"),
+            cc: header_value(&headers, "Cc"),
+            reply_to: header_value(&headers, "Reply-To"),
+            return_path: header_value(&headers, "Return-Path"),
+            list_id: header_value(&headers, "List-Id"),
+            message_timestamp,
+            timestamp_source,
+            source_file: message.source.file.to_string(),
+            folder: message.source.folder.to_string(),
+            occurrence: 0,
+            headers: headers.iter().map(|header| json!([header.get_key(), header.get_value()])).collect()
+        })
+    }
+}
+
+fn header_value(headers: &[MailHeader], key: &str) -> Option<String> {
+    headers.get_first_value(key)
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Lists the addresses of the From headers, including the members of groups. A message may name several authors, but
+/// only the first is stored as its sender and the others are logged at debug level.
+fn parse_senders(headers: &[MailHeader]) -> Vec<SingleInfo> {
+    headers.iter()
+        .filter(|header| header.get_key_ref().eq_ignore_ascii_case("From"))
+        .filter_map(|header| addrparse_header(header).ok())
+        .flat_map(|addresses| addresses.into_inner())
+        .flat_map(|address| match address {
+            MailAddr::Single(info) => vec![info],
+            MailAddr::Group(group) => group.addrs
+        })
+        .collect()
+}
+
+fn parse_fallback_address(message: &RawMessage, headers: &[MailHeader]) -> String {
+    headers.get_first_value("From")
+        .and_then(|from| RE.find(&from).map(|address| address.as_str().to_string()))
+        .unwrap_or_else(|| match &message.envelope {
+            Envelope::Mbox { sender, .. } => sender.clone(),
+            Envelope::Maildir { .. } => String::new()
+        })
+}
+
+pub fn registrable_domain(domain: &str) -> String {
+    psl::domain_str(domain).unwrap_or(domain).to_string()
+}
+
+/// Identifies a message by its Message-ID and body, so that copies of it found in other folders or exports are
+/// recognised as duplicates while different messages reusing a Message-ID, as spam often does, are kept apart.
+fn parse_message_key(body_hash: &str, message_id: Option<&str>) -> String {
+    match message_id {
+        Some(message_id) => format!("{} body-sha256:{}", message_id, body_hash),
+        None => format!("body-sha256:{}", body_hash)
+    }
+}
+
+/// Hashes the body with line endings unified and trailing whitespace removed, as mbox and Maildir copies of the same
+/// message differ in both.
+fn normalized_body_hash(body: &[u8]) -> String {
+    let mut lines = body.split(|byte| *byte == b'\n')
+        .map(|line| line.trim_ascii_end())
+        .collect::<Vec<_>>();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let mut hasher = Sha256::new();
+    for line in lines {
+        hasher.update(line);
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn parse_message_timestamp(message: &RawMessage, headers: &[MailHeader]) -> Result<(DateTime<FixedOffset>, TimestampSource), MessageError> {
+    let (envelope_timestamp, envelope_description) = match &message.envelope {
+        Envelope::Mbox { date, .. } => (
+            parse_separator_date(date).map(|timestamp| (timestamp, TimestampSource::MboxSeparator)),
+            format!("unrecognized mbox separator date {:?}", date)
+        ),
+        Envelope::Maildir { delivered_at } => (
+            delivered_at.map(|timestamp| (timestamp, TimestampSource::MaildirFilename)),
+            "no delivery time in Maildir file name".to_string()
+        )
+    };
+    if let Some(envelope_timestamp) = envelope_timestamp {
+        return Ok(envelope_timestamp);
+    }
+    match headers.get_first_value("Date") {
+        Some(date) => match parse_date_header(&date) {
+            Some(message_timestamp) => Ok((message_timestamp, TimestampSource::DateHeader)),
+            None => Err(MessageError::DateParse(format!("{} and Date header {:?}", envelope_description, date)))
+        },
+        None => Err(MessageError::DateParse(format!("{} and no Date header", envelope_description)))
+    }
+}
+
+fn parse_separator_date(raw_date: &str) -> Option<DateTime<FixedOffset>> {
+    SEPARATOR_DATE_FORMATS.iter()
+        .find_map(|format| DateTime::parse_from_str(raw_date, format).ok())
+        .or_else(|| parse_zone_abbreviation_date(raw_date))
+        .or_else(|| {
+            SEPARATOR_ASCTIME_FORMATS.iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(raw_date, format).ok())
+                .map(|timestamp| Utc.from_utc_datetime(&timestamp).into())
+        })
+}
+
+/// Parses an asctime date with a zone abbreviation in any position, most often between the time and the year.
+fn parse_zone_abbreviation_date(raw_date: &str) -> Option<DateTime<FixedOffset>> {
+    let mut fields = raw_date.split_whitespace().collect::<Vec<_>>();
+    let position = fields.iter().position(|field| zone_offset(field).is_some())?;
+    let hours = zone_offset(fields.remove(position))?;
+    let timestamp = NaiveDateTime::parse_from_str(&fields.join(" "), SEPARATOR_ASCTIME_FORMATS[0]).ok()?;
+    FixedOffset::east_opt(hours * 3600)?.from_local_datetime(&timestamp).single()
+}
+
+fn zone_offset(abbreviation: &str) -> Option<i32> {
+    ZONE_ABBREVIATIONS.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(abbreviation))
+        .map(|(_, hours)| *hours)
+}
+
+fn parse_date_header(date: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(message_timestamp) = DateTime::parse_from_rfc2822(date.trim()) {
+        return Some(message_timestamp);
+    }
+    // dateparse returns the epoch rather than an error when it finds no date tokens at all
+    match dateparse(date).ok()? {
+        0 => None,
+        timestamp => Utc.timestamp_opt(timestamp, 0).single().map(|message_timestamp| message_timestamp.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_separator_dates() {
+        let cases = [
+            ("Mon Jan 02 10:00:00 +0100 2023", Some("2023-01-02T10:00:00+01:00")),
+            ("Mon Jan 02 10:00:00 2023 -0500", Some("2023-01-02T10:00:00-05:00")),
+            ("Mon Jan 02 10:00:00 UTC 2023", Some("2023-01-02T10:00:00+00:00")),
+            ("Mon Jan  2 10:00:00 EST 2023", Some("2023-01-02T10:00:00-05:00")),
+            ("Mon Jan 02 10:00:00 2023 PDT", Some("2023-01-02T10:00:00-07:00")),
+            ("Mon Jan  2 10:00:00 2023", Some("2023-01-02T10:00:00+00:00")),
+            ("Mon Jan  2 10:00 2023", Some("2023-01-02T10:00:00+00:00")),
+            ("Mon Jan 02 10:00:00 CEST 2023", None),
+            ("not a date", None)
+        ];
+        for (raw_date, expected) in cases {
+            let parsed = parse_separator_date(raw_date).map(|timestamp| timestamp.to_rfc3339());
+            assert_eq!(parsed.as_deref(), expected, "{}", raw_date);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::{error::Error, fmt::Display, io, sync::Arc};
+
+use mailparse::MailParseError;
+use serde_json::{json, Value};
+
+use crate::{export::ExportError, storage::StorageError};
+
+#[derive(Debug)]
+pub struct InboxParserError {
+    pub failed_email_count: usize
+}
+
+impl Display for InboxParserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to parse inbox completely: {} failed emails.", self.failed_email_count)
+    }
+}
+
+impl Error for InboxParserError {}
+
+#[derive(Debug, Clone)]
+pub enum MessageError {
+    Read(Arc<io::Error>),
+    HeaderParse(Arc<MailParseError>),
+    DateParse(String),
+    DbInsert(Arc<StorageError>),
+    ExportWrite(Arc<ExportError>)
+}
+
+impl MessageError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MessageError::Read(_) => "read",
+            MessageError::HeaderParse(_) => "header_parse",
+            MessageError::DateParse(_) => "date_parse",
+            MessageError::DbInsert(_) => "db_insert",
+            MessageError::ExportWrite(_) => "export_write"
+        }
+    }
+}
+
+impl Display for MessageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageError::Read(error) => write!(f, "Failed to read message: {}", error),
+            MessageError::HeaderParse(error) => write!(f, "Failed to parse headers: {}", error),
+            MessageError::DateParse(reason) => write!(f, "Failed to parse timestamp: {}", reason),
+            MessageError::DbInsert(error) => write!(f, "Failed to insert into database: {}", error),
+            MessageError::ExportWrite(error) => write!(f, "Failed to write to output file: {}", error)
+        }
+    }
+}
+
+impl Error for MessageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MessageError::Read(error) => Some(error.as_ref()),
+            MessageError::HeaderParse(error) => Some(error.as_ref()),
+            MessageError::DateParse(_) => None,
+            MessageError::DbInsert(error) => Some(error.as_ref()),
+            MessageError::ExportWrite(error) => Some(error.as_ref())
+        }
+    }
+}
+
+impl From<MailParseError> for MessageError {
+    fn from(error: MailParseError) -> Self {
+        MessageError::HeaderParse(Arc::new(error))
+    }
+}
+
+#[derive(Debug)]
+pub struct MessageFailure {
+    pub source_file: Arc<str>,
+    pub index: usize,
+    pub offset: usize,
+    pub error: MessageError
+}
+
+impl MessageFailure {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "source_file": &*self.source_file,
+            "index": self.index,
+            "offset": self.offset,
+            "kind": self.error.kind(),
+            "error": self.error.to_string()
+        })
+    }
+}
+
+impl Display for MessageFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Message {} at byte {} of {}: {}", self.index, self.offset, self.source_file, self.error)
+    }
+}
@@ -0,0 +1,41 @@
+use std::{error::Error, fs::File, sync::Arc};
+
+use csv::Writer;
+
+use super::{field_values, ExportError, PartialFile, FIELDS};
+use crate::{email::EmailEntry, error::MessageError, import::{BatchCounts, EmailSink}};
+
+pub struct CsvExporter {
+    file: PartialFile,
+    writer: Option<Writer<File>>
+}
+
+impl CsvExporter {
+    pub fn create(file: PartialFile) -> Result<CsvExporter, ExportError> {
+        let mut writer = Writer::from_writer(file.create()?);
+        writer.write_record(FIELDS)?;
+        Ok(CsvExporter { file, writer: Some(writer) })
+    }
+
+    fn write_rows(&mut self, emails: &[&EmailEntry]) -> Result<(), ExportError> {
+        let writer = self.writer.as_mut().expect("export already finished");
+        for email in emails {
+            writer.write_record(field_values(email).iter().map(|value| value.as_deref().unwrap_or("")))?;
+        }
+        Ok(())
+    }
+}
+
+impl EmailSink for CsvExporter {
+    fn write_batch(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, MessageError> {
+        self.write_rows(emails).map_err(|error| MessageError::ExportWrite(Arc::new(error)))?;
+        Ok(BatchCounts { inserted: emails.len() as u64, duplicates: 0 })
+    }
+
+    fn finish(&mut self, keep: bool) -> Result<(), Box<dyn Error>> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        Ok(self.file.finish(keep)?)
+    }
+}
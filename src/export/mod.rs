@@ -0,0 +1,163 @@
+mod csv;
+mod ndjson;
+mod parquet;
+
+use std::{error::Error, fmt::Display, fs::{self, File}, io, path::{Path, PathBuf}};
+
+use clap::ValueEnum;
+
+use crate::{email::EmailEntry, import::EmailSink};
+
+use self::{csv::CsvExporter, ndjson::NdjsonExporter, parquet::ParquetExporter};
+
+const FIELDS: [&str; 20] = [
+    "message_key", "message_id", "address", "display_name", "local_part", "domain", "registrable_domain", "subject",
+    "to_addresses", "cc_addresses", "reply_to", "return_path", "list_id", "timestamp", "timestamp_source",
+    "source_file", "folder", "content_hash", "body_hash", "headers"
+];
+
+const TIMESTAMP_FIELD: usize = 13;
+
+const HEADERS_FIELD: usize = 19;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Csv,
+    Ndjson,
+    Parquet
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &Path) -> Option<OutputFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "csv" => Some(OutputFormat::Csv),
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            "parquet" => Some(OutputFormat::Parquet),
+            _ => None
+        }
+    }
+}
+
+pub fn create(path: &Path, format: OutputFormat) -> Result<Box<dyn EmailSink>, ExportError> {
+    let file = PartialFile::new(path);
+    Ok(match format {
+        OutputFormat::Csv => Box::new(CsvExporter::create(file)?),
+        OutputFormat::Ndjson => Box::new(NdjsonExporter::create(file)?),
+        OutputFormat::Parquet => Box::new(ParquetExporter::create(file)?)
+    })
+}
+
+fn field_values(email: &EmailEntry) -> [Option<String>; 20] {
+    [
+        Some(email.message_key.clone()),
+        email.message_id.clone(),
+        Some(email.address.clone()),
+        email.display_name.clone(),
+        Some(email.local_part.clone()),
+        Some(email.domain.clone()),
+        Some(email.registrable_domain.clone()),
+        email.subject.clone(),
+        email.to.clone(),
+        email.cc.clone(),
+        email.reply_to.clone(),
+        email.return_path.clone(),
+        email.list_id.clone(),
+        Some(email.message_timestamp.to_rfc3339()),
+        Some(email.timestamp_source.as_str().to_string()),
+        Some(email.source_file.clone()),
+        Some(email.folder.clone()),
+        Some(email.content_hash.clone()),
+        Some(email.body_hash.clone()),
+        Some(email.headers.to_string())
+    ]
+}
+
+/// An output file that is written under a temporary name and only moved into place once the export completes, so
+/// an interrupted or rolled back import never leaves a truncated file behind.
+struct PartialFile {
+    path: PathBuf,
+    partial_path: PathBuf
+}
+
+impl PartialFile {
+    fn new(path: &Path) -> PartialFile {
+        let mut partial_path = path.as_os_str().to_owned();
+        partial_path.push(".partial");
+        PartialFile { path: path.to_path_buf(), partial_path: partial_path.into() }
+    }
+
+    fn create(&self) -> io::Result<File> {
+        File::create(&self.partial_path)
+    }
+
+    fn finish(&self, keep: bool) -> io::Result<()> {
+        if keep {
+            fs::rename(&self.partial_path, &self.path)
+        } else {
+            fs::remove_file(&self.partial_path)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Csv(::csv::Error),
+    Json(serde_json::Error),
+    Parquet(::parquet::errors::ParquetError),
+    Arrow(arrow_schema::ArrowError)
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Io(error) => write!(f, "{}", error),
+            ExportError::Csv(error) => write!(f, "{}", error),
+            ExportError::Json(error) => write!(f, "{}", error),
+            ExportError::Parquet(error) => write!(f, "{}", error),
+            ExportError::Arrow(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl Error for ExportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExportError::Io(error) => Some(error),
+            ExportError::Csv(error) => Some(error),
+            ExportError::Json(error) => Some(error),
+            ExportError::Parquet(error) => Some(error),
+            ExportError::Arrow(error) => Some(error)
+        }
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(error: io::Error) -> Self {
+        ExportError::Io(error)
+    }
+}
+
+impl From<::csv::Error> for ExportError {
+    fn from(error: ::csv::Error) -> Self {
+        ExportError::Csv(error)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(error: serde_json::Error) -> Self {
+        ExportError::Json(error)
+    }
+}
+
+impl From<::parquet::errors::ParquetError> for ExportError {
+    fn from(error: ::parquet::errors::ParquetError) -> Self {
+        ExportError::Parquet(error)
+    }
+}
+
+impl From<arrow_schema::ArrowError> for ExportError {
+    fn from(error: arrow_schema::ArrowError) -> Self {
+        ExportError::Arrow(error)
+    }
+}
@@ -0,0 +1,47 @@
+use std::{error::Error, fs::File, io::{BufWriter, Write}, sync::Arc};
+
+use serde_json::{Map, Value};
+
+use super::{field_values, ExportError, PartialFile, FIELDS, HEADERS_FIELD};
+use crate::{email::EmailEntry, error::MessageError, import::{BatchCounts, EmailSink}};
+
+pub struct NdjsonExporter {
+    file: PartialFile,
+    writer: Option<BufWriter<File>>
+}
+
+impl NdjsonExporter {
+    pub fn create(file: PartialFile) -> Result<NdjsonExporter, ExportError> {
+        let writer = BufWriter::new(file.create()?);
+        Ok(NdjsonExporter { file, writer: Some(writer) })
+    }
+
+    fn write_rows(&mut self, emails: &[&EmailEntry]) -> Result<(), ExportError> {
+        let writer = self.writer.as_mut().expect("export already finished");
+        for email in emails {
+            let mut object = FIELDS.iter()
+                .zip(field_values(email))
+                .map(|(field, value)| (field.to_string(), value.map_or(Value::Null, Value::String)))
+                .collect::<Map<_, _>>();
+            // Headers are nested as JSON rather than a string holding JSON, as in the other formats
+            object.insert(FIELDS[HEADERS_FIELD].to_string(), email.headers.clone());
+            serde_json::to_writer(&mut *writer, &object)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl EmailSink for NdjsonExporter {
+    fn write_batch(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, MessageError> {
+        self.write_rows(emails).map_err(|error| MessageError::ExportWrite(Arc::new(error)))?;
+        Ok(BatchCounts { inserted: emails.len() as u64, duplicates: 0 })
+    }
+
+    fn finish(&mut self, keep: bool) -> Result<(), Box<dyn Error>> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        Ok(self.file.finish(keep)?)
+    }
+}
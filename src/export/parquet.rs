@@ -0,0 +1,61 @@
+use std::{error::Error, fs::File, sync::Arc};
+
+use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+
+use super::{field_values, ExportError, PartialFile, FIELDS, TIMESTAMP_FIELD};
+use crate::{email::EmailEntry, error::MessageError, import::{BatchCounts, EmailSink}};
+
+pub struct ParquetExporter {
+    file: PartialFile,
+    schema: SchemaRef,
+    writer: Option<ArrowWriter<File>>
+}
+
+impl ParquetExporter {
+    pub fn create(file: PartialFile) -> Result<ParquetExporter, ExportError> {
+        let fields = FIELDS.iter()
+            .enumerate()
+            .map(|(index, name)| match index {
+                TIMESTAMP_FIELD => Field::new(*name, DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), false),
+                _ => Field::new(*name, DataType::Utf8, true)
+            })
+            .collect::<Vec<_>>();
+        let schema = Arc::new(Schema::new(fields));
+        let writer = ArrowWriter::try_new(file.create()?, schema.clone(), None)?;
+        Ok(ParquetExporter { file, schema, writer: Some(writer) })
+    }
+
+    fn write_rows(&mut self, emails: &[&EmailEntry]) -> Result<(), ExportError> {
+        let rows = emails.iter().map(|email| field_values(email)).collect::<Vec<_>>();
+        let columns = (0..FIELDS.len())
+            .map(|index| -> ArrayRef {
+                match index {
+                    TIMESTAMP_FIELD => {
+                        let timestamps = emails.iter().map(|email| email.message_timestamp.timestamp_micros()).collect::<Vec<_>>();
+                        Arc::new(TimestampMicrosecondArray::from(timestamps).with_timezone("UTC"))
+                    }
+                    _ => Arc::new(rows.iter().map(|row| row[index].as_deref()).collect::<StringArray>())
+                }
+            })
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.as_mut().expect("export already finished").write(&batch)?;
+        Ok(())
+    }
+}
+
+impl EmailSink for ParquetExporter {
+    fn write_batch(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, MessageError> {
+        self.write_rows(emails).map_err(|error| MessageError::ExportWrite(Arc::new(error)))?;
+        Ok(BatchCounts { inserted: emails.len() as u64, duplicates: 0 })
+    }
+
+    fn finish(&mut self, keep: bool) -> Result<(), Box<dyn Error>> {
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(self.file.finish(keep)?)
+    }
+}
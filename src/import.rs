@@ -0,0 +1,169 @@
+use std::{collections::HashMap, error::Error};
+
+use rayon::{prelude::*, ThreadPoolBuilder};
+use tracing::{debug, warn};
+
+use crate::{email::EmailEntry, error::{MessageError, MessageFailure}, input::RawMessage};
+
+pub trait EmailSink {
+    /// Writes a batch of emails and returns how many of them were new or copies of a stored message.
+    fn write_batch(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, MessageError>;
+
+    /// Whether nothing of a failed batch was kept, so its messages can be written again one at a time.
+    fn can_retry(&self) -> bool {
+        false
+    }
+
+    /// Completes the import, discarding everything written if `keep` is false.
+    fn finish(&mut self, keep: bool) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct BatchCounts {
+    pub inserted: u64,
+    /// Messages already stored from another location, such as a second folder or export
+    pub duplicates: u64
+}
+
+struct PendingEmail {
+    index: usize,
+    offset: usize,
+    email: EmailEntry
+}
+
+/// Numbers the copies of each message within the file being read, as mailboxes are read one file at a time.
+#[derive(Default)]
+struct Occurrences {
+    file: String,
+    counts: HashMap<String, usize>
+}
+
+impl Occurrences {
+    fn next(&mut self, email: &EmailEntry) -> usize {
+        if email.source_file != self.file {
+            self.file.clone_from(&email.source_file);
+            self.counts.clear();
+        }
+        let count = self.counts.entry(email.message_key.clone()).or_insert(0);
+        *count += 1;
+        *count - 1
+    }
+}
+
+#[derive(Default)]
+pub struct ImportStats {
+    pub inserted: u64,
+    pub duplicates: u64,
+    pub already_imported: u64,
+    pub failures: Vec<MessageFailure>
+}
+
+pub fn import_messages(
+    sink: &mut dyn EmailSink,
+    messages: impl Iterator<Item = Result<RawMessage, MessageFailure>>,
+    batch_size: usize,
+    jobs: usize,
+    atomic: bool
+) -> Result<ImportStats, Box<dyn Error>> {
+    let pool = ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    let mut stats = ImportStats::default();
+    let mut batch = Vec::new();
+    let mut occurrences = Occurrences::default();
+    let mut messages = messages.peekable();
+
+    // Messages are read one chunk at a time and parsed on the pool, which keeps memory bounded and hands the results
+    // to the sink in mailbox order
+    while messages.peek().is_some() {
+        let chunk = messages.by_ref().take(batch_size.max(1)).collect::<Vec<_>>();
+        let parsed = pool.install(|| chunk.into_par_iter().map(parse).collect::<Vec<_>>());
+        for result in parsed {
+            match result {
+                Ok(mut pending) => {
+                    pending.email.occurrence = occurrences.next(&pending.email);
+                    batch.push(pending);
+                }
+                Err(failure) => stats.record_failure(failure)
+            }
+            if batch.len() >= batch_size {
+                stats.flush(sink, &mut batch);
+            }
+        }
+    }
+    stats.flush(sink, &mut batch);
+
+    let keep = !atomic || stats.failures.is_empty();
+    sink.finish(keep)?;
+    if !keep {
+        stats.inserted = 0;
+        warn!("Rolled back the import because some emails failed to process");
+    }
+    Ok(stats)
+}
+
+fn parse(message: Result<RawMessage, MessageFailure>) -> Result<PendingEmail, MessageFailure> {
+    let message = message?;
+    match EmailEntry::parse(&message) {
+        Ok(email) => Ok(PendingEmail { index: message.index, offset: message.offset, email }),
+        Err(error) => Err(MessageFailure {
+            source_file: message.source.file,
+            index: message.index,
+            offset: message.offset,
+            error
+        })
+    }
+}
+
+impl ImportStats {
+    fn record_failure(&mut self, failure: MessageFailure) {
+        warn!(
+            source_file = %failure.source_file,
+            index = failure.index,
+            offset = failure.offset,
+            kind = failure.error.kind(),
+            error = %failure.error,
+            "Failed to process message"
+        );
+        self.failures.push(failure);
+    }
+
+    /// Writes a batch, retrying it one message at a time if it fails and the sink allows it, as a single message the
+    /// database rejects would otherwise fail every message of its batch.
+    fn flush(&mut self, sink: &mut dyn EmailSink, batch: &mut Vec<PendingEmail>) {
+        if batch.is_empty() {
+            return;
+        }
+        match self.write(sink, batch) {
+            Ok(()) => {}
+            Err(error) if !sink.can_retry() || batch.len() == 1 => self.record_batch_failure(batch, &error),
+            Err(error) => {
+                debug!(messages = batch.len(), error = %error, "Retrying failed batch one message at a time");
+                for pending in batch.chunks(1) {
+                    if let Err(error) = self.write(sink, pending) {
+                        self.record_batch_failure(pending, &error);
+                    }
+                }
+            }
+        }
+        batch.clear();
+    }
+
+    fn write(&mut self, sink: &mut dyn EmailSink, batch: &[PendingEmail]) -> Result<(), MessageError> {
+        let emails = batch.iter().map(|pending| &pending.email).collect::<Vec<_>>();
+        let counts = sink.write_batch(&emails)?;
+        debug!(messages = batch.len(), inserted = counts.inserted, duplicates = counts.duplicates, "Wrote batch");
+        self.inserted += counts.inserted;
+        self.duplicates += counts.duplicates;
+        self.already_imported += batch.len() as u64 - counts.inserted - counts.duplicates;
+        Ok(())
+    }
+
+    fn record_batch_failure(&mut self, batch: &[PendingEmail], error: &MessageError) {
+        for pending in batch {
+            self.record_failure(MessageFailure {
+                source_file: pending.email.source_file.as_str().into(),
+                index: pending.index,
+                offset: pending.offset,
+                error: error.clone()
+            });
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::{fs::File, io::{self, BufRead, BufReader}, path::Path};
+
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+
+use crate::progress::{ByteCounter, CountingReader};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+/// Opens a file for streaming, decompressing gzip, zstd and xz archives as they are read.
+pub fn open(path: &Path, counter: &ByteCounter) -> io::Result<Box<dyn BufRead>> {
+    decompress(BufReader::new(CountingReader::new(File::open(path)?, counter)))
+}
+
+/// Wraps a stream in a decoder for its compression format, which is detected from its first bytes rather than a file
+/// extension.
+pub fn decompress<R: BufRead + 'static>(mut file: R) -> io::Result<Box<dyn BufRead>> {
+    let magic = file.fill_buf()?;
+    if magic.starts_with(GZIP_MAGIC) {
+        // Multi-member archives are common when mailboxes are appended to with `gzip -c >>`
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(file)?)))
+    } else if magic.starts_with(XZ_MAGIC) {
+        Ok(Box::new(BufReader::new(XzDecoder::new_multi_decoder(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
@@ -0,0 +1,92 @@
+use std::{fs, io::{self, Read}, path::{Path, PathBuf}, sync::Arc};
+
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+
+use super::{compression, display_path, Envelope, MessageSource, RawMessage};
+use crate::{error::{MessageError, MessageFailure}, progress::ByteCounter};
+
+const SUBDIRECTORIES: [&str; 2] = ["new", "cur"];
+
+pub struct Maildir {
+    path: PathBuf,
+    folder: Arc<str>,
+    files: Vec<PathBuf>,
+    counter: ByteCounter
+}
+
+impl Maildir {
+    pub fn is_maildir(path: &Path) -> bool {
+        path.is_dir() && SUBDIRECTORIES.iter().any(|subdirectory| path.join(subdirectory).is_dir())
+    }
+
+    pub fn open(path: &Path, folder: Arc<str>, counter: &ByteCounter) -> io::Result<Maildir> {
+        let mut files = list_messages(path)?;
+        files.sort_by_key(|file| (delivered_at(file), file.file_name().map(|name| name.to_owned())));
+        Ok(Maildir { path: path.to_path_buf(), folder, files, counter: counter.clone() })
+    }
+
+    /// Total size of the message files, for estimating how much of an import is left.
+    pub fn size(path: &Path) -> io::Result<u64> {
+        let mut size = 0;
+        for file in list_messages(path)? {
+            size += fs::metadata(file)?.len();
+        }
+        Ok(size)
+    }
+
+    pub fn messages(self) -> impl Iterator<Item = Result<RawMessage, MessageFailure>> {
+        let (path, folder, counter) = (self.path, self.folder, self.counter);
+        self.files.into_iter()
+            .enumerate()
+            .map(move |(index, file)| match read_message(&file, &counter) {
+                Ok(data) => Ok(RawMessage {
+                    source: MessageSource { file: location(&path, &file), folder: folder.clone() },
+                    index,
+                    offset: 0,
+                    envelope: Envelope::Maildir { delivered_at: delivered_at(&file) },
+                    data
+                }),
+                Err(error) => Err(MessageFailure { source_file: display_path(&file), index, offset: 0, error: MessageError::Read(Arc::new(error)) })
+            })
+    }
+}
+
+/// Lists the delivered messages in `new` and `cur`, ignoring `tmp` which only holds deliveries in progress.
+fn list_messages(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for subdirectory in SUBDIRECTORIES {
+        let subdirectory = path.join(subdirectory);
+        if !subdirectory.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(subdirectory)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && !entry.file_name().to_string_lossy().starts_with('.') {
+                files.push(entry.path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Reads a message file, which may be compressed as with Dovecot's zlib plugin.
+fn read_message(file: &Path, counter: &ByteCounter) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    compression::open(file, counter)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Identifies a message file by its Maildir and its name up to the `:2,` flags, as mail clients move it from `new` to
+/// `cur` once it has been seen and rename it whenever its flags change.
+fn location(path: &Path, file: &Path) -> Arc<str> {
+    let name = file.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let unique = name.split_once(':').map_or(&*name, |(unique, _)| unique);
+    display_path(&path.join(unique))
+}
+
+/// Maildir file names start with the Unix time of delivery, e.g. `1672653600.M20P1234.host:2,S`.
+fn delivered_at(file: &Path) -> Option<DateTime<FixedOffset>> {
+    let name = file.file_name()?.to_str()?;
+    let seconds = name.split('.').next()?.parse::<i64>().ok()?;
+    Utc.timestamp_opt(seconds, 0).single().map(|delivered_at| delivered_at.into())
+}
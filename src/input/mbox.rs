@@ -0,0 +1,390 @@
+use std::{collections::VecDeque, io::{self, BufRead, BufReader, ErrorKind, Read}, path::Path, sync::Arc};
+
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use regex::bytes::Regex;
+
+use super::{compression, display_path, Envelope, MessageSource, RawMessage, STDIN_PATH};
+use crate::{error::{MessageError, MessageFailure}, progress::{ByteCounter, CountingReader}};
+
+/// Content-Length values above this are taken as wrong without reading that far, which bounds the memory used to check one
+const MAX_CONTENT_LENGTH: usize = 256 * 1024 * 1024;
+
+lazy_static! {
+    // The date must have a time and a year, in either order, so body lines such as `From 10:30 to 11:00` that were
+    // written without quoting do not start a message
+    static ref SEPARATOR: Regex = Regex::new(
+        r"^From (?P<sender>\S*) +(?P<date>.*(?:\b\d{1,2}:\d{2}\b.*\b(?:19|20)\d{2}\b|\b(?:19|20)\d{2}\b.*\b\d{1,2}:\d{2}\b).*?)\s*$"
+    ).unwrap();
+    static ref QUOTED_FROM: Regex = Regex::new(r"^>+From ").unwrap();
+}
+
+/// The mbox dialect, which decides how messages are framed and how `From ` lines inside them are quoted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MboxFormat {
+    /// Detect Content-Length framing per message and undo mboxrd quoting otherwise
+    Auto,
+    /// Split on `From ` lines, unquoting `>From `
+    Mboxo,
+    /// Split on `From ` lines, unquoting `>From `, `>>From `, ...
+    Mboxrd,
+    /// Frame by Content-Length, unquoting `>From `
+    Mboxcl,
+    /// Frame by Content-Length without any quoting
+    Mboxcl2
+}
+
+impl MboxFormat {
+    fn uses_content_length(self) -> bool {
+        matches!(self, MboxFormat::Auto | MboxFormat::Mboxcl | MboxFormat::Mboxcl2)
+    }
+
+    fn unquote(self, line: &[u8], content_length_framed: bool) -> &[u8] {
+        let quoted = match self {
+            MboxFormat::Auto => !content_length_framed && QUOTED_FROM.is_match(line),
+            MboxFormat::Mboxrd => QUOTED_FROM.is_match(line),
+            MboxFormat::Mboxo | MboxFormat::Mboxcl => line.starts_with(b">From "),
+            MboxFormat::Mboxcl2 => false
+        };
+        if quoted { &line[1..] } else { line }
+    }
+}
+
+pub struct MboxReader<R> {
+    source: Source<R>,
+    message_source: MessageSource,
+    format: MboxFormat,
+    index: usize,
+    done: bool
+}
+
+pub fn open(path: &Path, folder: Arc<str>, format: MboxFormat, counter: &ByteCounter) -> io::Result<MboxReader<Box<dyn BufRead>>> {
+    MboxReader::new(compression::open(path, counter)?, MessageSource { file: display_path(path), folder }, format)
+}
+
+pub fn open_stdin(folder: Arc<str>, format: MboxFormat, counter: &ByteCounter) -> io::Result<MboxReader<Box<dyn BufRead>>> {
+    let stdin = BufReader::new(CountingReader::new(io::stdin(), counter));
+    MboxReader::new(compression::decompress(stdin)?, MessageSource { file: display_path(Path::new(STDIN_PATH)), folder }, format)
+}
+
+impl<R: BufRead> MboxReader<R> {
+    pub fn new(mut reader: R, message_source: MessageSource, format: MboxFormat) -> io::Result<MboxReader<R>> {
+        let start = reader.fill_buf()?;
+        if !start.is_empty() && !start.starts_with(b"From ") {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not an mbox file: it does not start with a \"From \" line"));
+        }
+        Ok(MboxReader { source: Source { reader, pushback: VecDeque::new(), offset: 0 }, message_source, format, index: 0, done: false })
+    }
+
+    fn read_message(&mut self) -> io::Result<Option<RawMessage>> {
+        let mut line = Vec::new();
+        let offset = loop {
+            let offset = self.source.offset;
+            if self.source.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if SEPARATOR.is_match(&line) {
+                break offset;
+            }
+        };
+        let envelope = parse_separator(&line);
+
+        let mut data = Vec::new();
+        let mut content_length = None;
+        loop {
+            if self.source.read_line(&mut line)? == 0 {
+                break;
+            }
+            if SEPARATOR.is_match(&line) {
+                self.source.unread(&line);
+                break;
+            }
+            if let Some(value) = header_value(&line, b"content-length") {
+                content_length = value.parse::<usize>().ok().filter(|length| *length <= MAX_CONTENT_LENGTH);
+            }
+            data.extend_from_slice(self.format.unquote(&line, false));
+            if is_blank(&line) {
+                break;
+            }
+        }
+
+        let mut content_length_framed = false;
+        if let (true, Some(length)) = (self.format.uses_content_length(), content_length) {
+            let mut body = Vec::new();
+            self.source.read_up_to(length, &mut body)?;
+            if body.len() < length || !self.at_boundary()? {
+                // The Content-Length header is wrong, so give the body back and split on From lines instead
+                self.source.unread(&body);
+            } else {
+                for line in body.split_inclusive(|byte| *byte == b'\n') {
+                    data.extend_from_slice(self.format.unquote(line, true));
+                }
+                content_length_framed = true;
+            }
+        }
+        if !content_length_framed {
+            loop {
+                if self.source.read_line(&mut line)? == 0 {
+                    break;
+                }
+                if SEPARATOR.is_match(&line) {
+                    self.source.unread(&line);
+                    break;
+                }
+                data.extend_from_slice(self.format.unquote(&line, false));
+            }
+        }
+
+        let message = RawMessage { source: self.message_source.clone(), index: self.index, offset, envelope, data };
+        self.index += 1;
+        Ok(Some(message))
+    }
+
+    /// Whether the stream is at the end of a message: end of input or a `From ` line, optionally after a blank line.
+    fn at_boundary(&mut self) -> io::Result<bool> {
+        let mut read = Vec::new();
+        let mut line = Vec::new();
+        let mut boundary = false;
+        for _ in 0..2 {
+            if self.source.read_line(&mut line)? == 0 || SEPARATOR.is_match(&line) {
+                boundary = true;
+            }
+            read.extend_from_slice(&line);
+            if boundary || !is_blank(&line) {
+                break;
+            }
+        }
+        self.source.unread(&read);
+        Ok(boundary)
+    }
+}
+
+impl<R: BufRead> Iterator for MboxReader<R> {
+    type Item = Result<RawMessage, MessageFailure>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let offset = self.source.offset;
+        match self.read_message() {
+            Ok(Some(message)) => Some(Ok(message)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                // The stream position is unknown after a read error, so nothing after it can be framed reliably
+                self.done = true;
+                Some(Err(MessageFailure {
+                    source_file: self.message_source.file.clone(),
+                    index: self.index,
+                    offset,
+                    error: MessageError::Read(Arc::new(error))
+                }))
+            }
+        }
+    }
+}
+
+/// Line-oriented reader that can push bytes back, so framing decisions can look ahead without seeking.
+struct Source<R> {
+    reader: R,
+    pushback: VecDeque<u8>,
+    offset: usize
+}
+
+impl<R: BufRead> Source<R> {
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        line.clear();
+        match self.pushback.iter().position(|byte| *byte == b'\n') {
+            Some(end) => line.extend(self.pushback.drain(..=end)),
+            None => {
+                line.extend(self.pushback.drain(..));
+                self.reader.read_until(b'\n', line)?;
+            }
+        }
+        self.offset += line.len();
+        Ok(line.len())
+    }
+
+    fn read_up_to(&mut self, length: usize, buffer: &mut Vec<u8>) -> io::Result<()> {
+        let from_pushback = length.min(self.pushback.len());
+        buffer.extend(self.pushback.drain(..from_pushback));
+        (&mut self.reader).take((length - from_pushback) as u64).read_to_end(buffer)?;
+        self.offset += buffer.len();
+        Ok(())
+    }
+
+    fn unread(&mut self, data: &[u8]) {
+        for byte in data.iter().rev() {
+            self.pushback.push_front(*byte);
+        }
+        self.offset -= data.len();
+    }
+}
+
+fn parse_separator(line: &[u8]) -> Envelope {
+    let captures = SEPARATOR.captures(line);
+    let capture = |name| {
+        captures.as_ref()
+            .and_then(|captures| captures.name(name))
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+            .unwrap_or_default()
+    };
+    Envelope::Mbox { sender: capture("sender"), date: capture("date") }
+}
+
+fn header_value(line: &[u8], name: &[u8]) -> Option<String> {
+    let (key, value) = line.split_at(line.iter().position(|byte| *byte == b':')?);
+    if !key.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&value[1..]).trim().to_string())
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line == b"\n" || line == b"\r\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(mbox: &str, format: MboxFormat) -> Vec<RawMessage> {
+        let source = MessageSource { file: "test.mbox".into(), folder: "test".into() };
+        MboxReader::new(mbox.as_bytes(), source, format).unwrap()
+            .map(|message| message.unwrap())
+            .collect()
+    }
+
+    fn read_data(mbox: &str, format: MboxFormat) -> Vec<String> {
+        read(mbox, format).into_iter()
+            .map(|message| String::from_utf8(message.data).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_separators() {
+        let mbox = "From alice@example.com Mon Jan  2 10:00:00 2023\nSubject: one\n\nFirst\n\n\
+            From bob@example.com Tue Jan  3 11:00:00 +0100 2023\nSubject: two\n\nSecond\n";
+        let messages = read(mbox, MboxFormat::Auto);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].data, b"Subject: one\n\nFirst\n\n");
+        assert_eq!(messages[1].data, b"Subject: two\n\nSecond\n");
+        assert_eq!((messages[0].index, messages[0].offset), (0, 0));
+        assert_eq!((messages[1].index, messages[1].offset), (1, mbox.find("From bob").unwrap()));
+        let Envelope::Mbox { sender, date } = &messages[1].envelope else { panic!("not an mbox envelope") };
+        assert_eq!((sender.as_str(), date.as_str()), ("bob@example.com", "Tue Jan  3 11:00:00 +0100 2023"));
+    }
+
+    #[test]
+    fn recognizes_separators() {
+        let cases = [
+            ("From alice@example.com Mon Jan  2 10:00:00 2023\n", true),
+            ("From alice@example.com Mon Jan 02 10:00:00 +0000 2023\r\n", true),
+            ("From alice@example.com Mon Jan  2 10:00 2023\n", true),
+            ("From  Mon Jan  2 10:00:00 2023\n", true),
+            ("From alice@example.com Mon, 2 Jan 2023 10:00:00 +0000\n", true),
+            ("From 10:30 to 11:00 we are in the meeting room\n", false),
+            ("From here on, everything changes\n", false),
+            (">From alice@example.com Mon Jan  2 10:00:00 2023\n", false)
+        ];
+        for (line, expected) in cases {
+            assert_eq!(SEPARATOR.is_match(line.as_bytes()), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn keeps_body_lines_that_look_like_separators() {
+        let mbox = "From alice@example.com Mon Jan  2 10:00:00 2023\nSubject: one\n\n\
+            From 10:30 to 11:00 we meet\nFrom here on\n\n\
+            From bob@example.com Tue Jan  3 11:00:00 2023\nSubject: two\n\nSecond\n";
+        let messages = read_data(mbox, MboxFormat::Mboxo);
+        assert_eq!(messages, [
+            "Subject: one\n\nFrom 10:30 to 11:00 we meet\nFrom here on\n\n",
+            "Subject: two\n\nSecond\n"
+        ]);
+    }
+
+    #[test]
+    fn unquotes_from_lines() {
+        let mbox = "From alice@example.com Mon Jan  2 10:00:00 2023\nSubject: one\n\n\
+            >From the start\n>>From the quote\n>>>From deeper\n";
+        let cases = [
+            (MboxFormat::Mboxo, "From the start\n>>From the quote\n>>>From deeper\n"),
+            (MboxFormat::Mboxrd, "From the start\n>From the quote\n>>From deeper\n"),
+            (MboxFormat::Mboxcl, "From the start\n>>From the quote\n>>>From deeper\n"),
+            (MboxFormat::Mboxcl2, ">From the start\n>>From the quote\n>>>From deeper\n"),
+            (MboxFormat::Auto, "From the start\n>From the quote\n>>From deeper\n")
+        ];
+        for (format, body) in cases {
+            assert_eq!(read_data(mbox, format), [format!("Subject: one\n\n{}", body)], "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn frames_by_content_length() {
+        let body = "From alice@example.com Mon Jan  2 09:00:00 2023\n>From the start\n";
+        let mbox = format!(
+            "From alice@example.com Mon Jan  2 10:00:00 2023\nContent-Length: {}\n\n{}\n\
+            From bob@example.com Tue Jan  3 11:00:00 2023\nSubject: two\n\nSecond\n",
+            body.len(), body
+        );
+        let cases = [
+            (MboxFormat::Mboxcl, "From alice@example.com Mon Jan  2 09:00:00 2023\nFrom the start\n"),
+            (MboxFormat::Mboxcl2, body),
+            (MboxFormat::Auto, body)
+        ];
+        for (format, expected) in cases {
+            let messages = read_data(&mbox, format);
+            assert_eq!(messages.len(), 2, "{:?}", format);
+            assert_eq!(messages[0], format!("Content-Length: {}\n\n{}", body.len(), expected));
+            assert_eq!(messages[1], "Subject: two\n\nSecond\n");
+        }
+    }
+
+    #[test]
+    fn splits_on_separators_when_content_length_is_wrong() {
+        for length in [5, 500, 999_999_999_999_999, usize::MAX] {
+            let mbox = format!(
+                "From alice@example.com Mon Jan  2 10:00:00 2023\nContent-Length: {}\n\n>From the start\nFirst\n\n\
+                From bob@example.com Tue Jan  3 11:00:00 2023\nSubject: two\n\nSecond\n",
+                length
+            );
+            for format in [MboxFormat::Auto, MboxFormat::Mboxcl, MboxFormat::Mboxcl2] {
+                let messages = read_data(&mbox, format);
+                let first_line = if format == MboxFormat::Mboxcl2 { ">From the start" } else { "From the start" };
+                assert_eq!(messages, [
+                    format!("Content-Length: {}\n\n{}\nFirst\n\n", length, first_line),
+                    "Subject: two\n\nSecond\n".to_string()
+                ], "{:?} with Content-Length {}", format, length);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_crlf_line_endings() {
+        let body = ">From the start\r\nFirst\r\n";
+        let mbox = format!(
+            "From alice@example.com Mon Jan  2 10:00:00 2023\r\nContent-Length: {}\r\n\r\n{}\r\n\
+            From bob@example.com Tue Jan  3 11:00:00 2023\r\nSubject: two\r\n\r\n>From the start\r\n",
+            body.len(), body
+        );
+        assert_eq!(read_data(&mbox, MboxFormat::Auto), [
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body),
+            "Subject: two\r\n\r\nFrom the start\r\n".to_string()
+        ]);
+        assert_eq!(read_data(&mbox, MboxFormat::Mboxrd), [
+            format!("Content-Length: {}\r\n\r\nFrom the start\r\nFirst\r\n\r\n", body.len()),
+            "Subject: two\r\n\r\nFrom the start\r\n".to_string()
+        ]);
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_mboxes() {
+        let source = MessageSource { file: "test.mbox".into(), folder: "test".into() };
+        assert!(MboxReader::new(&b"Subject: hello\n\nbody\n"[..], source.clone(), MboxFormat::Auto).is_err());
+        assert_eq!(MboxReader::new(&b""[..], source, MboxFormat::Auto).unwrap().count(), 0);
+    }
+}
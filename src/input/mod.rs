@@ -0,0 +1,124 @@
+mod compression;
+mod maildir;
+mod mbox;
+mod walk;
+
+use std::{fs, io::{self, BufRead}, path::{Path, PathBuf}, sync::Arc};
+
+use chrono::{DateTime, FixedOffset};
+use tracing::debug;
+
+use crate::{error::{MessageError, MessageFailure}, progress::ByteCounter};
+
+use self::{maildir::Maildir, mbox::MboxReader};
+
+pub use self::{mbox::MboxFormat, walk::find_mailboxes};
+
+/// Input path that reads a single mbox stream from standard input
+pub const STDIN_PATH: &str = "-";
+
+pub struct RawMessage {
+    pub source: MessageSource,
+    pub index: usize,
+    pub offset: usize,
+    pub envelope: Envelope,
+    pub data: Vec<u8>
+}
+
+/// The file a message was read from and the mail folder it belongs to
+#[derive(Clone)]
+pub struct MessageSource {
+    pub file: Arc<str>,
+    pub folder: Arc<str>
+}
+
+pub enum Envelope {
+    /// The sender and date of an mbox `From ` separator line
+    Mbox { sender: String, date: String },
+    /// The delivery time encoded in a Maildir file name
+    Maildir { delivered_at: Option<DateTime<FixedOffset>> }
+}
+
+/// An mbox file or Maildir directory to import, and the folder name its messages are recorded under
+pub struct MailboxPath {
+    pub path: PathBuf,
+    pub folder: String
+}
+
+impl MailboxPath {
+    /// Size in bytes of the files holding the mailbox, which is unknown for stdin. Mailboxes that cannot be read
+    /// count as empty, as they fail without reading anything.
+    fn size(&self) -> Option<u64> {
+        if self.path.as_os_str() == STDIN_PATH {
+            return None;
+        }
+        let size = if Maildir::is_maildir(&self.path) {
+            Maildir::size(&self.path)
+        } else {
+            fs::metadata(&self.path).map(|metadata| metadata.len())
+        };
+        Some(size.unwrap_or(0))
+    }
+}
+
+pub enum Mailbox {
+    Mbox(MboxReader<Box<dyn BufRead>>),
+    Maildir(Maildir)
+}
+
+impl Mailbox {
+    pub fn open(mailbox: &MailboxPath, mbox_format: MboxFormat, counter: &ByteCounter) -> io::Result<Mailbox> {
+        let folder: Arc<str> = mailbox.folder.as_str().into();
+        if mailbox.path.as_os_str() == STDIN_PATH {
+            return Ok(Mailbox::Mbox(mbox::open_stdin(folder, mbox_format, counter)?));
+        }
+        // Messages are recorded under the canonical path of their file, so importing the same file through another
+        // path does not make copies of them
+        let path = fs::canonicalize(&mailbox.path)?;
+        if Maildir::is_maildir(&path) {
+            Ok(Mailbox::Maildir(Maildir::open(&path, folder, counter)?))
+        } else {
+            Ok(Mailbox::Mbox(mbox::open(&path, folder, mbox_format, counter)?))
+        }
+    }
+
+    pub fn messages(self) -> Box<dyn Iterator<Item = Result<RawMessage, MessageFailure>>> {
+        match self {
+            Mailbox::Mbox(mbox) => Box::new(mbox),
+            Mailbox::Maildir(maildir) => Box::new(maildir.messages())
+        }
+    }
+}
+
+/// Reads the messages of each mailbox in turn. A mailbox that cannot be opened is reported as a single failure so the
+/// remaining mailboxes are still imported.
+pub fn read_mailboxes(
+    mailboxes: Vec<MailboxPath>,
+    mbox_format: MboxFormat,
+    counter: ByteCounter
+) -> impl Iterator<Item = Result<RawMessage, MessageFailure>> {
+    mailboxes.into_iter().flat_map(move |mailbox| {
+        debug!(path = %mailbox.path.display(), folder = mailbox.folder, "Reading mailbox");
+        match Mailbox::open(&mailbox, mbox_format, &counter) {
+            Ok(opened) => opened.messages(),
+            Err(error) => Box::new(std::iter::once(Err(MessageFailure {
+                source_file: display_path(&mailbox.path),
+                index: 0,
+                offset: 0,
+                error: MessageError::Read(Arc::new(error))
+            })))
+        }
+    })
+}
+
+/// Combined size of the mailboxes in bytes, or `None` when reading from stdin.
+pub fn total_size(mailboxes: &[MailboxPath]) -> Option<u64> {
+    mailboxes.iter().map(|mailbox| mailbox.size()).sum()
+}
+
+fn display_path(path: &Path) -> Arc<str> {
+    if path.as_os_str() == STDIN_PATH {
+        return "<stdin>".into();
+    }
+    path.to_string_lossy().into()
+}
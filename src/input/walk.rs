@@ -0,0 +1,109 @@
+use std::{fs, io::{self, BufRead}, path::{Path, PathBuf}};
+
+use glob::Pattern;
+use tracing::{debug, warn};
+
+use crate::progress::ByteCounter;
+
+use super::{compression, maildir::Maildir, MailboxPath, STDIN_PATH};
+
+/// Extensions stripped from mbox file names to get their folder name, outermost first.
+const MAILBOX_EXTENSIONS: [&str; 4] = [".gz", ".zst", ".xz", ".mbox"];
+
+/// Resolves the given paths into mailboxes. Files and Maildir directories are used as they are, while other
+/// directories are searched recursively for mbox files and Maildirs, such as a Thunderbird profile's `Mail` folder,
+/// skipping any path or file name that matches an `exclude` pattern, any file or directory that cannot be read, and
+/// symbolic links to directories, which could lead back up the tree.
+pub fn find_mailboxes(paths: &[PathBuf], exclude: &[Pattern]) -> io::Result<Vec<MailboxPath>> {
+    let mut mailboxes = Vec::new();
+    for path in paths {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if path.as_os_str() == STDIN_PATH {
+            mailboxes.push(MailboxPath { path: path.clone(), folder: "stdin".to_string() });
+        } else if Maildir::is_maildir(path) {
+            push_maildir(path.clone(), folder_name(&name), &[], exclude, &mut mailboxes).map_err(|error| with_path(path, error))?;
+        } else if !path.is_dir() {
+            mailboxes.push(MailboxPath { path: path.clone(), folder: folder_name(&name) });
+        } else {
+            walk(path, exclude, &mut Vec::new(), &mut mailboxes).map_err(|error| with_path(path, error))?;
+        }
+    }
+    Ok(mailboxes)
+}
+
+fn walk(directory: &Path, exclude: &[Pattern], folders: &mut Vec<String>, mailboxes: &mut Vec<MailboxPath>) -> io::Result<()> {
+    let mut entries = fs::read_dir(directory)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || exclude.iter().any(|pattern| pattern.matches(&name) || pattern.matches_path(&path)) {
+            continue;
+        }
+        if path.is_dir() && entry.file_type().is_ok_and(|file_type| file_type.is_symlink()) {
+            debug!(path = %path.display(), "Skipping symbolic link to a directory");
+        } else if path.is_dir() {
+            // Thunderbird keeps the subfolders of folder `Inbox` in a sibling directory named `Inbox.sbd`
+            folders.push(folder_name(name.strip_suffix(".sbd").unwrap_or(&name)));
+            let searched = if Maildir::is_maildir(&path) {
+                push_maildir(path.clone(), folders.join("/"), folders, exclude, mailboxes)
+            } else {
+                walk(&path, exclude, folders, mailboxes)
+            };
+            if let Err(error) = searched {
+                warn!(path = %path.display(), error = %error, "Skipping unreadable directory");
+            }
+            folders.pop();
+        } else if !name.ends_with(".msf") {
+            match looks_like_mbox(&path) {
+                Ok(true) => {
+                    folders.push(folder_name(&name));
+                    mailboxes.push(MailboxPath { path, folder: folders.join("/") });
+                    folders.pop();
+                }
+                Ok(false) => {}
+                Err(error) => warn!(path = %path.display(), error = %error, "Skipping unreadable file")
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds a Maildir along with the Maildir++ subfolders that Dovecot and Courier keep inside it, such as `.Sent` or
+/// `.Lists.rust` for the folder `Lists/rust`, naming the subfolders below `parents`.
+fn push_maildir(path: PathBuf, folder: String, parents: &[String], exclude: &[Pattern], mailboxes: &mut Vec<MailboxPath>) -> io::Result<()> {
+    let mut entries = fs::read_dir(&path)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    mailboxes.push(MailboxPath { path, folder });
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(subfolder) = name.strip_prefix('.').filter(|subfolder| !subfolder.is_empty()) else {
+            continue;
+        };
+        let excluded = exclude.iter().any(|pattern| pattern.matches(subfolder) || pattern.matches(&name) || pattern.matches_path(&path));
+        if !excluded && Maildir::is_maildir(&path) {
+            let folder = parents.iter().map(String::as_str).chain(subfolder.split('.')).collect::<Vec<_>>().join("/");
+            mailboxes.push(MailboxPath { path, folder });
+        }
+    }
+    Ok(())
+}
+
+/// Whether a file found while searching a directory is an mbox, as opposed to the indexes, filters and settings that
+/// mail clients keep alongside their folders.
+fn looks_like_mbox(path: &Path) -> io::Result<bool> {
+    Ok(compression::open(path, &ByteCounter::default())?.fill_buf()?.starts_with(b"From "))
+}
+
+fn with_path(path: &Path, error: io::Error) -> io::Error {
+    io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+fn folder_name(file_name: &str) -> String {
+    let mut folder = file_name;
+    for extension in MAILBOX_EXTENSIONS {
+        folder = folder.strip_suffix(extension).unwrap_or(folder);
+    }
+    folder.to_string()
+}
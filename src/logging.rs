@@ -0,0 +1,32 @@
+use std::io::{self, IsTerminal};
+
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per event
+    Json
+}
+
+/// Sends log events to stderr, at INFO by default, raised by each `-v` and lowered by each `-q`.
+pub fn init(verbose: u8, quiet: u8, format: LogFormat) {
+    let level = match 2 + i16::from(verbose) - i16::from(quiet) {
+        i16::MIN..=0 => LevelFilter::ERROR,
+        1 => LevelFilter::WARN,
+        2 => LevelFilter::INFO,
+        3 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_max_level(level)
+        .with_target(false);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init()
+    }
+}
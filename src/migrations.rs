@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+
+use crate::storage::{Storage, StorageError};
+
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str
+}
+
+pub struct MigrationStatus {
+    pub migration: &'static Migration,
+    pub applied_at: Option<DateTime<Utc>>
+}
+
+pub fn status(storage: &mut dyn Storage) -> Result<Vec<MigrationStatus>, StorageError> {
+    let applied = storage.applied_migrations()?;
+    Ok(storage.migrations().iter()
+        .map(|migration| MigrationStatus {
+            migration,
+            applied_at: applied.iter()
+                .find(|(version, _)| *version == migration.version)
+                .map(|(_, applied_at)| *applied_at)
+        })
+        .collect())
+}
+
+pub fn up(storage: &mut dyn Storage) -> Result<Vec<&'static Migration>, StorageError> {
+    let pending = status(storage)?
+        .into_iter()
+        .filter(|status| status.applied_at.is_none())
+        .map(|status| status.migration)
+        .collect::<Vec<_>>();
+    for migration in &pending {
+        storage.apply_migration(migration)?;
+    }
+    Ok(pending)
+}
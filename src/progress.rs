@@ -0,0 +1,152 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Read},
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    time::{Duration, Instant}
+};
+
+use tracing::{info, warn};
+
+use crate::{error::MessageFailure, import::ImportStats};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of bytes read from the input files, shared between the readers and the progress reporter.
+#[derive(Clone, Default)]
+pub struct ByteCounter(Arc<AtomicU64>);
+
+impl ByteCounter {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts the bytes read from a file before any decompression, so progress can be compared to file sizes.
+pub struct CountingReader<R> {
+    inner: R,
+    counter: ByteCounter
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R, counter: &ByteCounter) -> CountingReader<R> {
+        CountingReader { inner, counter: counter.clone() }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.counter.0.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// Passes messages through while logging progress every few seconds.
+pub struct Progress<I> {
+    messages: I,
+    bytes: ByteCounter,
+    total_bytes: Option<u64>,
+    count: u64,
+    started: Instant,
+    last_report: Instant
+}
+
+impl<I> Progress<I> {
+    pub fn new(messages: I, bytes: ByteCounter, total_bytes: Option<u64>) -> Progress<I> {
+        let started = Instant::now();
+        Progress { messages, bytes, total_bytes, count: 0, started, last_report: started }
+    }
+
+    fn report(&self) {
+        let elapsed = self.started.elapsed();
+        let bytes = self.bytes.get();
+        let messages_per_second = (self.count as f64 / elapsed.as_secs_f64()) as u64;
+        let read_per_second = format_bytes((bytes as f64 / elapsed.as_secs_f64()) as u64);
+        match self.total_bytes.filter(|total_bytes| *total_bytes > 0 && bytes > 0) {
+            Some(total_bytes) => {
+                let fraction = (bytes as f64 / total_bytes as f64).min(1.0);
+                let remaining = elapsed.mul_f64((1.0 - fraction) / fraction);
+                info!(
+                    messages = self.count,
+                    read = %format_bytes(bytes),
+                    total = %format_bytes(total_bytes),
+                    percent = (fraction * 100.0) as u64,
+                    messages_per_second,
+                    read_per_second = %read_per_second,
+                    eta = %format_duration(remaining),
+                    "Import progress"
+                );
+            }
+            None => info!(
+                messages = self.count,
+                read = %format_bytes(bytes),
+                messages_per_second,
+                read_per_second = %read_per_second,
+                "Import progress"
+            )
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for Progress<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message = self.messages.next()?;
+        self.count += 1;
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report();
+            self.last_report = Instant::now();
+        }
+        Some(message)
+    }
+}
+
+pub fn log_summary(stats: &ImportStats, elapsed: Duration, bytes: u64) {
+    let processed = stats.inserted + stats.duplicates + stats.already_imported + stats.failures.len() as u64;
+    info!(
+        inserted = stats.inserted,
+        duplicates = stats.duplicates,
+        already_imported = stats.already_imported,
+        failed = stats.failures.len(),
+        elapsed = %format_duration(elapsed),
+        messages_per_second = (processed as f64 / elapsed.as_secs_f64()) as u64,
+        read_per_second = %format_bytes((bytes as f64 / elapsed.as_secs_f64()) as u64),
+        "Import finished"
+    );
+    for (kind, count) in failure_counts(&stats.failures) {
+        warn!(kind, count, "Messages failed to process");
+    }
+}
+
+fn failure_counts(failures: &[MessageFailure]) -> BTreeMap<&'static str, usize> {
+    let mut kinds = BTreeMap::new();
+    for failure in failures {
+        *kinds.entry(failure.error.kind()).or_insert(0) += 1;
+    }
+    kinds
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60)
+    }
+}
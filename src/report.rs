@@ -0,0 +1,131 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use crate::{config::Config, email::registrable_domain, storage::{Storage, StorageError}};
+
+pub struct ReportRow {
+    pub key: String,
+    pub message_count: i64,
+    /// Copies of these messages found in other folders or mailboxes
+    pub duplicate_count: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>
+}
+
+impl ReportRow {
+    pub fn messages_per_day(&self) -> f64 {
+        let days = (self.last_seen - self.first_seen).num_seconds() as f64 / 86400.0;
+        self.message_count as f64 / days.max(1.0)
+    }
+
+    fn merge(&mut self, other: ReportRow) {
+        self.message_count += other.message_count;
+        self.duplicate_count += other.duplicate_count;
+        self.first_seen = self.first_seen.min(other.first_seen);
+        self.last_seen = self.last_seen.max(other.last_seen);
+    }
+}
+
+/// Exclusions and domain aliases from the config file, applied to the rows read from the database.
+#[derive(Default)]
+pub struct ReportRules {
+    exclude_senders: HashSet<String>,
+    exclude_domains: HashSet<String>,
+    domain_aliases: HashMap<String, String>
+}
+
+impl ReportRules {
+    pub fn from_config(config: &Config) -> ReportRules {
+        ReportRules {
+            exclude_senders: config.report.exclude_senders.iter().map(|sender| sender.to_lowercase()).collect(),
+            exclude_domains: config.report.exclude_domains.iter().map(|domain| domain.to_lowercase()).collect(),
+            domain_aliases: config.domain_aliases.iter()
+                .map(|(domain, alias)| (domain.to_lowercase(), alias.to_lowercase()))
+                .collect()
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.exclude_senders.is_empty() && self.exclude_domains.is_empty() && self.domain_aliases.is_empty()
+    }
+
+    fn alias<'a>(&'a self, domain: &'a str) -> &'a str {
+        self.domain_aliases.get(domain).map(String::as_str).unwrap_or(domain)
+    }
+
+    fn excludes_domain(&self, domain: &str) -> bool {
+        self.exclude_domains.contains(domain) || self.exclude_domains.contains(self.alias(domain))
+    }
+
+    fn excludes_sender(&self, address: &str) -> bool {
+        let address = address.to_lowercase();
+        let domain = address.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default();
+        self.exclude_senders.contains(&address) || self.excludes_domain(domain) || self.excludes_domain(&registrable_domain(domain))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ReportKey {
+    Sender,
+    Domain
+}
+
+impl ReportKey {
+    pub fn sql(&self) -> &'static str {
+        match self {
+            ReportKey::Sender => "address",
+            ReportKey::Domain => "coalesce(registrable_domain, domain)"
+        }
+    }
+}
+
+pub fn top_senders(storage: &mut dyn Storage, folder: Option<&str>, limit: i64, rules: &ReportRules) -> Result<Vec<ReportRow>, StorageError> {
+    if rules.is_empty() {
+        return storage.top_by(ReportKey::Sender, folder, Some(limit));
+    }
+    Ok(storage.top_by(ReportKey::Sender, folder, None)?
+        .into_iter()
+        .filter(|row| !rules.excludes_sender(&row.key))
+        .take(limit.max(0) as usize)
+        .collect())
+}
+
+pub fn top_domains(storage: &mut dyn Storage, folder: Option<&str>, limit: i64, rules: &ReportRules) -> Result<Vec<ReportRow>, StorageError> {
+    if rules.is_empty() {
+        return storage.top_by(ReportKey::Domain, folder, Some(limit));
+    }
+    let mut domains: HashMap<String, ReportRow> = HashMap::new();
+    for mut row in storage.top_by(ReportKey::Domain, folder, None)? {
+        if rules.excludes_domain(&row.key) {
+            continue;
+        }
+        row.key = rules.alias(&row.key).to_string();
+        match domains.entry(row.key.clone()) {
+            Entry::Occupied(mut entry) => entry.get_mut().merge(row),
+            Entry::Vacant(entry) => {
+                entry.insert(row);
+            }
+        }
+    }
+    let mut rows = domains.into_values().collect::<Vec<_>>();
+    rows.sort_by(|a, b| b.message_count.cmp(&a.message_count).then_with(|| a.key.cmp(&b.key)));
+    rows.truncate(limit.max(0) as usize);
+    Ok(rows)
+}
+
+pub fn print_table(title: &str, key_header: &str, rows: &[ReportRow]) {
+    println!("{}", title);
+    println!("{:>8}  {:>8}  {:>8}  {:<19}  {:<19}  {}", "MESSAGES", "DUPES", "PER DAY", "FIRST SEEN", "LAST SEEN", key_header);
+    for row in rows {
+        println!(
+            "{:>8}  {:>8}  {:>8.2}  {:<19}  {:<19}  {}",
+            row.message_count,
+            row.duplicate_count,
+            row.messages_per_day(),
+            row.first_seen.format("%Y-%m-%d %H:%M:%S"),
+            row.last_seen.format("%Y-%m-%d %H:%M:%S"),
+            row.key
+        );
+    }
+}
@@ -0,0 +1,154 @@
+mod postgres;
+mod sqlite;
+
+use std::{error::Error, fmt::Display, io, path::PathBuf, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use tracing::info;
+
+use crate::{email::EmailEntry, error::MessageError, import::{BatchCounts, EmailSink}, migrations::Migration, report::{ReportKey, ReportRow}};
+
+pub use self::{postgres::{PostgresStorage, SslMode, TlsOptions}, sqlite::SqliteStorage};
+
+pub const EMAIL_COLUMNS: &str = "message_key, message_id, address, display_name, local_part, domain, registrable_domain, subject, \
+    to_addresses, cc_addresses, reply_to, return_path, list_id, timestamp, timestamp_source, source_file, folder, content_hash, body_hash, headers";
+
+pub trait Storage {
+    fn migrations(&self) -> &'static [Migration];
+
+    fn applied_migrations(&mut self) -> Result<Vec<(i32, DateTime<Utc>)>, StorageError>;
+
+    fn apply_migration(&mut self, migration: &Migration) -> Result<(), StorageError>;
+
+    fn batch_execute(&mut self, sql: &str) -> Result<(), StorageError>;
+
+    /// Inserts the emails inside the caller's transaction, skipping any whose message key is already stored, and
+    /// records the location each was read from in `message_locations`.
+    fn insert_emails(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, StorageError>;
+
+    /// Ranks keys by message count, counting only messages found in `folder` when one is given, whether or not it is
+    /// the folder they were first stored from, and returns every key when there is no limit.
+    fn top_by(&mut self, key: ReportKey, folder: Option<&str>, limit: Option<i64>) -> Result<Vec<ReportRow>, StorageError>;
+}
+
+pub fn connect(database_url: &str, tls: &TlsOptions) -> Result<Box<dyn Storage>, StorageError> {
+    if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        let storage = PostgresStorage::connect(database_url, tls)?;
+        info!(backend = "postgres", "Connected to database");
+        return Ok(Box::new(storage));
+    }
+    let path = database_url.strip_prefix("sqlite://")
+        .or_else(|| database_url.strip_prefix("sqlite:"))
+        .unwrap_or(database_url);
+    let storage = SqliteStorage::open(path)?;
+    info!(backend = "sqlite", path, "Opened database");
+    Ok(Box::new(storage))
+}
+
+/// Writes imported emails to a database, one transaction per batch, or inside a single transaction for atomic
+/// imports.
+pub struct DatabaseSink<'a> {
+    storage: &'a mut dyn Storage,
+    atomic: bool
+}
+
+impl<'a> DatabaseSink<'a> {
+    pub fn new(storage: &'a mut dyn Storage, atomic: bool) -> Result<DatabaseSink<'a>, StorageError> {
+        if atomic {
+            storage.batch_execute("BEGIN")?;
+        }
+        Ok(DatabaseSink { storage, atomic })
+    }
+
+    fn write_transaction(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, StorageError> {
+        let (begin, commit, rollback) = if self.atomic {
+            ("SAVEPOINT batch", "RELEASE SAVEPOINT batch", "ROLLBACK TO SAVEPOINT batch")
+        } else {
+            ("BEGIN", "COMMIT", "ROLLBACK")
+        };
+        self.storage.batch_execute(begin)?;
+        match self.storage.insert_emails(emails) {
+            Ok(counts) => {
+                self.storage.batch_execute(commit)?;
+                Ok(counts)
+            }
+            Err(error) => {
+                self.storage.batch_execute(rollback)?;
+                Err(error)
+            }
+        }
+    }
+}
+
+impl EmailSink for DatabaseSink<'_> {
+    fn write_batch(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, MessageError> {
+        self.write_transaction(emails).map_err(|error| MessageError::DbInsert(Arc::new(error)))
+    }
+
+    /// A failed batch is rolled back, but retrying it is pointless in an atomic import, which is rolled back as a whole.
+    fn can_retry(&self) -> bool {
+        !self.atomic
+    }
+
+    fn finish(&mut self, keep: bool) -> Result<(), Box<dyn Error>> {
+        if self.atomic {
+            self.storage.batch_execute(if keep { "COMMIT" } else { "ROLLBACK" })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Postgres(::postgres::Error),
+    Sqlite(rusqlite::Error),
+    Tls(native_tls::Error),
+    RootCert(PathBuf, io::Error),
+    MissingRootCert
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // The server's message is only in the source of a database error, which otherwise displays as `db error`
+            StorageError::Postgres(error) => match error.as_db_error() {
+                Some(db_error) => write!(f, "{}", db_error),
+                None => write!(f, "{}", error)
+            },
+            StorageError::Sqlite(error) => write!(f, "{}", error),
+            StorageError::Tls(error) => write!(f, "{}", error),
+            StorageError::RootCert(path, error) => write!(f, "Failed to read root certificate {}: {}", path.display(), error),
+            StorageError::MissingRootCert => write!(f, "sslmode verify-ca and verify-full require a root certificate, given with --ssl-root-cert or sslrootcert")
+        }
+    }
+}
+
+impl Error for StorageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StorageError::Postgres(error) => Some(error),
+            StorageError::Sqlite(error) => Some(error),
+            StorageError::Tls(error) => Some(error),
+            StorageError::RootCert(_, error) => Some(error),
+            StorageError::MissingRootCert => None
+        }
+    }
+}
+
+impl From<::postgres::Error> for StorageError {
+    fn from(error: ::postgres::Error) -> Self {
+        StorageError::Postgres(error)
+    }
+}
+
+impl From<native_tls::Error> for StorageError {
+    fn from(error: native_tls::Error) -> Self {
+        StorageError::Tls(error)
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(error: rusqlite::Error) -> Self {
+        StorageError::Sqlite(error)
+    }
+}
@@ -0,0 +1,248 @@
+use std::{fs, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use native_tls::{Certificate, TlsConnector};
+use postgres::{binary_copy::BinaryCopyInWriter, types::{ToSql, Type}, Client, Config, NoTls};
+use postgres_native_tls::MakeTlsConnector;
+use serde::Deserialize;
+
+use super::{Storage, StorageError, EMAIL_COLUMNS};
+use crate::{email::EmailEntry, import::BatchCounts, migrations::Migration, report::{ReportKey, ReportRow}};
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "create_emails", sql: include_str!("../../migrations/postgres/0001_create_emails.sql") },
+    Migration { version: 2, name: "add_message_key", sql: include_str!("../../migrations/postgres/0002_add_message_key.sql") },
+    Migration { version: 3, name: "add_header_columns", sql: include_str!("../../migrations/postgres/0003_add_header_columns.sql") },
+    Migration { version: 4, name: "split_sender_address", sql: include_str!("../../migrations/postgres/0004_split_sender_address.sql") },
+    Migration { version: 5, name: "add_registrable_domain", sql: include_str!("../../migrations/postgres/0005_add_registrable_domain.sql") },
+    Migration { version: 6, name: "add_timestamp_source", sql: include_str!("../../migrations/postgres/0006_add_timestamp_source.sql") },
+    Migration { version: 7, name: "add_message_source", sql: include_str!("../../migrations/postgres/0007_add_message_source.sql") },
+    Migration { version: 8, name: "add_content_hash", sql: include_str!("../../migrations/postgres/0008_add_content_hash.sql") },
+    Migration { version: 9, name: "add_message_locations", sql: include_str!("../../migrations/postgres/0009_add_message_locations.sql") },
+    Migration { version: 10, name: "add_headers", sql: include_str!("../../migrations/postgres/0010_add_headers.sql") }
+];
+
+/// Types of the staging table columns: the email columns followed by the occurrence of the message's location.
+const STAGING_COLUMN_TYPES: &[Type] = &[
+    Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR,
+    Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::TIMESTAMPTZ, Type::VARCHAR,
+    Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::JSONB, Type::INT4
+];
+
+/// How TLS is negotiated with the server, with the same meaning as libpq's `sslmode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    /// Never use TLS
+    Disable,
+    /// Use TLS if the server supports it, without verifying its certificate
+    Prefer,
+    /// Always use TLS, verifying the certificate only when a root certificate is given
+    Require,
+    /// Always use TLS and verify that the certificate is signed by the given root certificate, which is required
+    VerifyCa,
+    /// Like `VerifyCa`, and also verify that the certificate matches the host name
+    VerifyFull
+}
+
+impl SslMode {
+    fn from_param(value: &str) -> Option<SslMode> {
+        SslMode::from_str(value, false).ok()
+    }
+}
+
+#[derive(Default)]
+pub struct TlsOptions {
+    pub ssl_mode: Option<SslMode>,
+    /// PEM file with the CA certificates to trust in addition to the system ones
+    pub root_cert: Option<PathBuf>
+}
+
+pub struct PostgresStorage {
+    client: Client
+}
+
+impl PostgresStorage {
+    /// Connects with the TLS options given, falling back to `sslmode` and `sslrootcert` from the URL and then to
+    /// `prefer` without a custom root certificate.
+    pub fn connect(database_url: &str, tls: &TlsOptions) -> Result<PostgresStorage, StorageError> {
+        let (database_url, url_ssl_mode, url_root_cert) = split_tls_params(database_url);
+        let ssl_mode = tls.ssl_mode.or(url_ssl_mode).unwrap_or(SslMode::Prefer);
+        let root_cert = tls.root_cert.clone().or(url_root_cert);
+        let mut config = database_url.parse::<Config>()?;
+
+        if ssl_mode == SslMode::Disable {
+            config.ssl_mode(postgres::config::SslMode::Disable);
+            return Ok(PostgresStorage { client: config.connect(NoTls)? });
+        }
+        // Like libpq, verify against an explicitly given CA rather than any CA installed on the system
+        if matches!(ssl_mode, SslMode::VerifyCa | SslMode::VerifyFull) && root_cert.is_none() {
+            return Err(StorageError::MissingRootCert);
+        }
+        let mut connector = TlsConnector::builder();
+        if let Some(root_cert) = &root_cert {
+            let pem = fs::read(root_cert).map_err(|error| StorageError::RootCert(root_cert.clone(), error))?;
+            connector.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+        match ssl_mode {
+            SslMode::Prefer | SslMode::Require if root_cert.is_none() => {
+                connector.danger_accept_invalid_certs(true);
+            }
+            SslMode::Prefer | SslMode::Require | SslMode::VerifyCa => {
+                connector.danger_accept_invalid_hostnames(true);
+            }
+            SslMode::Disable | SslMode::VerifyFull => {}
+        }
+        config.ssl_mode(match ssl_mode {
+            SslMode::Prefer => postgres::config::SslMode::Prefer,
+            _ => postgres::config::SslMode::Require
+        });
+        Ok(PostgresStorage { client: config.connect(MakeTlsConnector::new(connector.build()?))? })
+    }
+}
+
+/// Removes the `sslmode` values and `sslrootcert` parameter that libpq accepts but the postgres crate rejects, so
+/// URLs copied from other tools keep working.
+fn split_tls_params(database_url: &str) -> (String, Option<SslMode>, Option<PathBuf>) {
+    let Some((base, query)) = database_url.split_once('?') else {
+        return (database_url.to_string(), None, None);
+    };
+    let (mut ssl_mode, mut root_cert) = (None, None);
+    let mut params = Vec::new();
+    for param in query.split('&') {
+        match param.split_once('=') {
+            Some(("sslmode", value)) if matches!(value, "verify-ca" | "verify-full") => ssl_mode = SslMode::from_param(value),
+            Some(("sslmode", value)) => {
+                ssl_mode = SslMode::from_param(value);
+                params.push(param);
+            }
+            Some(("sslrootcert", value)) => root_cert = Some(PathBuf::from(value)),
+            _ => params.push(param)
+        }
+    }
+    let database_url = if params.is_empty() { base.to_string() } else { format!("{}?{}", base, params.join("&")) };
+    (database_url, ssl_mode, root_cert)
+}
+
+impl Storage for PostgresStorage {
+    fn migrations(&self) -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    fn applied_migrations(&mut self) -> Result<Vec<(i32, DateTime<Utc>)>, StorageError> {
+        self.client.batch_execute("
+        CREATE TABLE IF NOT EXISTS schema_version (
+                version     INTEGER PRIMARY KEY,
+                name        VARCHAR NOT NULL,
+                applied_at  TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+                );
+        ")?;
+        Ok(self.client.query("SELECT version, applied_at FROM schema_version", &[])?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    fn apply_migration(&mut self, migration: &Migration) -> Result<(), StorageError> {
+        let mut transaction = self.client.transaction()?;
+        transaction.batch_execute(migration.sql)?;
+        transaction.execute("INSERT INTO schema_version (version, name) VALUES ($1, $2)", &[&migration.version, &migration.name])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn batch_execute(&mut self, sql: &str) -> Result<(), StorageError> {
+        Ok(self.client.batch_execute(sql)?)
+    }
+
+    fn insert_emails(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, StorageError> {
+        self.client.batch_execute(&format!("
+        CREATE TEMPORARY TABLE IF NOT EXISTS emails_staging AS
+        SELECT {EMAIL_COLUMNS}, 0 AS occurrence FROM emails WITH NO DATA
+        "))?;
+        let sink = self.client.copy_in(&format!("COPY emails_staging ({EMAIL_COLUMNS}, occurrence) FROM STDIN BINARY"))?;
+        let mut writer = BinaryCopyInWriter::new(sink, STAGING_COLUMN_TYPES);
+        for email in emails {
+            let timestamp_source = email.timestamp_source.as_str();
+            let occurrence = email.occurrence as i32;
+            let row: [&(dyn ToSql + Sync); 21] = [
+                &email.message_key, &email.message_id, &email.address, &email.display_name, &email.local_part, &email.domain, &email.registrable_domain, &email.subject,
+                &email.to, &email.cc, &email.reply_to, &email.return_path, &email.list_id, &email.message_timestamp, &timestamp_source,
+                &email.source_file, &email.folder, &email.content_hash, &email.body_hash, &email.headers, &occurrence
+            ];
+            writer.write(&row)?;
+        }
+        writer.finish()?;
+        let inserted = self.client.execute(&format!("
+        INSERT INTO emails ({EMAIL_COLUMNS})
+        SELECT {EMAIL_COLUMNS} FROM emails_staging
+        ON CONFLICT (message_key) DO NOTHING
+        "), &[])?;
+        let located = self.client.execute("
+        INSERT INTO message_locations (message_key, source_file, folder, occurrence)
+        SELECT message_key, source_file, folder, occurrence FROM emails_staging
+        ON CONFLICT DO NOTHING
+        ", &[])?;
+        self.client.batch_execute("TRUNCATE emails_staging")?;
+        Ok(BatchCounts { inserted, duplicates: located.saturating_sub(inserted) })
+    }
+
+    fn top_by(&mut self, key: ReportKey, folder: Option<&str>, limit: Option<i64>) -> Result<Vec<ReportRow>, StorageError> {
+        let query = format!("
+        SELECT {}, count(*), CAST(coalesce(sum(locations.copies - 1), 0) AS BIGINT), min(timestamp), max(timestamp)
+        FROM emails
+        LEFT JOIN (SELECT message_key, count(*) AS copies FROM message_locations GROUP BY 1) locations USING (message_key)
+        WHERE $1::VARCHAR IS NULL OR folder = $1
+        OR EXISTS (SELECT 1 FROM message_locations location WHERE location.message_key = emails.message_key AND location.folder = $1)
+        GROUP BY 1
+        ORDER BY 2 DESC, 1
+        LIMIT $2
+        ", key.sql());
+        Ok(self.client.query(&query, &[&folder, &limit])?
+            .iter()
+            .map(|row| ReportRow {
+                key: row.get(0),
+                message_count: row.get(1),
+                duplicate_count: row.get(2),
+                first_seen: row.get(3),
+                last_seen: row.get(4)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_tls_params() {
+        let cases = [
+            ("postgresql://user@host/db", "postgresql://user@host/db", None, None),
+            ("postgresql://user@host/db?sslmode=require", "postgresql://user@host/db?sslmode=require", Some(SslMode::Require), None),
+            ("postgresql://user@host/db?sslmode=disable", "postgresql://user@host/db?sslmode=disable", Some(SslMode::Disable), None),
+            ("postgresql://user@host/db?sslmode=verify-full", "postgresql://user@host/db", Some(SslMode::VerifyFull), None),
+            (
+                "postgresql://user@host/db?sslmode=verify-ca&sslrootcert=/etc/ca.pem",
+                "postgresql://user@host/db",
+                Some(SslMode::VerifyCa),
+                Some("/etc/ca.pem")
+            ),
+            (
+                "postgresql://user@host/db?application_name=inbox&sslrootcert=ca.pem&sslmode=prefer&connect_timeout=5",
+                "postgresql://user@host/db?application_name=inbox&sslmode=prefer&connect_timeout=5",
+                Some(SslMode::Prefer),
+                Some("ca.pem")
+            ),
+            ("postgresql://user@host/db?sslmode=sometimes", "postgresql://user@host/db?sslmode=sometimes", None, None),
+            ("postgresql://user@host/db?connect_timeout=5", "postgresql://user@host/db?connect_timeout=5", None, None)
+        ];
+        for (database_url, expected_url, expected_ssl_mode, expected_root_cert) in cases {
+            let (url, ssl_mode, root_cert) = split_tls_params(database_url);
+            assert_eq!(url, expected_url, "{}", database_url);
+            assert_eq!(ssl_mode, expected_ssl_mode, "{}", database_url);
+            assert_eq!(root_cert, expected_root_cert.map(PathBuf::from), "{}", database_url);
+        }
+    }
+}
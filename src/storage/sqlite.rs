@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+
+use super::{Storage, StorageError, EMAIL_COLUMNS};
+use crate::{email::EmailEntry, import::BatchCounts, migrations::Migration, report::{ReportKey, ReportRow}};
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "create_emails", sql: include_str!("../../migrations/sqlite/0001_create_emails.sql") },
+    Migration { version: 2, name: "add_message_key", sql: include_str!("../../migrations/sqlite/0002_add_message_key.sql") },
+    Migration { version: 3, name: "add_header_columns", sql: include_str!("../../migrations/sqlite/0003_add_header_columns.sql") },
+    Migration { version: 4, name: "split_sender_address", sql: include_str!("../../migrations/sqlite/0004_split_sender_address.sql") },
+    Migration { version: 5, name: "add_registrable_domain", sql: include_str!("../../migrations/sqlite/0005_add_registrable_domain.sql") },
+    Migration { version: 6, name: "add_timestamp_source", sql: include_str!("../../migrations/sqlite/0006_add_timestamp_source.sql") },
+    Migration { version: 7, name: "add_message_source", sql: include_str!("../../migrations/sqlite/0007_add_message_source.sql") },
+    Migration { version: 8, name: "add_content_hash", sql: include_str!("../../migrations/sqlite/0008_add_content_hash.sql") },
+    Migration { version: 9, name: "add_message_locations", sql: include_str!("../../migrations/sqlite/0009_add_message_locations.sql") },
+    Migration { version: 10, name: "add_headers", sql: include_str!("../../migrations/sqlite/0010_add_headers.sql") }
+];
+
+pub struct SqliteStorage {
+    connection: Connection
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<SqliteStorage, rusqlite::Error> {
+        Ok(SqliteStorage { connection: Connection::open(path)? })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn migrations(&self) -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    fn applied_migrations(&mut self) -> Result<Vec<(i32, DateTime<Utc>)>, StorageError> {
+        self.connection.execute_batch("
+        CREATE TABLE IF NOT EXISTS schema_version (
+                version     INTEGER PRIMARY KEY,
+                name        TEXT NOT NULL,
+                applied_at  TEXT NOT NULL
+                );
+        ")?;
+        let mut statement = self.connection.prepare("SELECT version, applied_at FROM schema_version")?;
+        let applied = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(applied)
+    }
+
+    fn apply_migration(&mut self, migration: &Migration) -> Result<(), StorageError> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute_batch(migration.sql)?;
+        transaction.execute("INSERT INTO schema_version (version, name, applied_at) VALUES (?1, ?2, ?3)", params![migration.version, migration.name, Utc::now()])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn batch_execute(&mut self, sql: &str) -> Result<(), StorageError> {
+        Ok(self.connection.execute_batch(sql)?)
+    }
+
+    fn insert_emails(&mut self, emails: &[&EmailEntry]) -> Result<BatchCounts, StorageError> {
+        let mut statement = self.connection.prepare_cached(&format!("
+        INSERT INTO emails ({EMAIL_COLUMNS})
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)
+        ON CONFLICT (message_key) DO NOTHING
+        "))?;
+        let mut locate = self.connection.prepare_cached("
+        INSERT INTO message_locations (message_key, source_file, folder, occurrence)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT DO NOTHING
+        ")?;
+        let (mut inserted, mut located) = (0, 0);
+        for email in emails {
+            inserted += statement.execute(params![
+                email.message_key, email.message_id, email.address, email.display_name, email.local_part, email.domain, email.registrable_domain, email.subject,
+                email.to, email.cc, email.reply_to, email.return_path, email.list_id, email.message_timestamp.with_timezone(&Utc), email.timestamp_source.as_str(),
+                email.source_file, email.folder, email.content_hash, email.body_hash, email.headers.to_string()
+            ])? as u64;
+            located += locate.execute(params![email.message_key, email.source_file, email.folder, email.occurrence as i64])? as u64;
+        }
+        Ok(BatchCounts { inserted, duplicates: located.saturating_sub(inserted) })
+    }
+
+    fn top_by(&mut self, key: ReportKey, folder: Option<&str>, limit: Option<i64>) -> Result<Vec<ReportRow>, StorageError> {
+        let mut statement = self.connection.prepare(&format!("
+        SELECT {}, count(*), CAST(coalesce(sum(locations.copies - 1), 0) AS BIGINT), min(timestamp), max(timestamp)
+        FROM emails
+        LEFT JOIN (SELECT message_key, count(*) AS copies FROM message_locations GROUP BY 1) locations USING (message_key)
+        WHERE ?1 IS NULL OR folder = ?1
+        OR EXISTS (SELECT 1 FROM message_locations location WHERE location.message_key = emails.message_key AND location.folder = ?1)
+        GROUP BY 1
+        ORDER BY 2 DESC, 1
+        LIMIT ?2
+        ", key.sql()))?;
+        // SQLite treats a negative limit as no limit
+        let rows = statement.query_map(params![folder, limit.unwrap_or(-1)], |row| {
+            Ok(ReportRow {
+                key: row.get(0)?,
+                message_count: row.get(1)?,
+                duplicate_count: row.get(2)?,
+                first_seen: row.get(3)?,
+                last_seen: row.get(4)?
+            })
+        })?.collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input::{Envelope, MessageSource, RawMessage}, migrations};
+
+    fn email(folder: &str, message_id: &str, body: &str) -> EmailEntry {
+        let data = format!("From: Spammer <spam@example.com>\nMessage-ID: {message_id}\nDate: Mon, 2 Jan 2023 10:00:00 +0000\n\n{body}\n");
+        EmailEntry::parse(&RawMessage {
+            source: MessageSource { file: format!("/mail/{folder}").into(), folder: folder.into() },
+            index: 0,
+            offset: 0,
+            envelope: Envelope::Mbox { sender: "spam@example.com".to_string(), date: "Mon Jan  2 10:00:00 2023".to_string() },
+            data: data.into_bytes()
+        }).unwrap()
+    }
+
+    fn insert(storage: &mut SqliteStorage, email: &EmailEntry) -> (u64, u64) {
+        let counts = storage.insert_emails(&[email]).unwrap();
+        (counts.inserted, counts.duplicates)
+    }
+
+    fn top_senders(storage: &mut SqliteStorage, folder: &str) -> Vec<(String, i64, i64)> {
+        storage.top_by(ReportKey::Sender, Some(folder), None).unwrap().into_iter()
+            .map(|row| (row.key, row.message_count, row.duplicate_count))
+            .collect()
+    }
+
+    #[test]
+    fn counts_duplicates_and_reimports() {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        migrations::up(&mut storage).unwrap();
+
+        let inbox = email("Inbox", "<1@example.com>", "Buy now");
+        assert_eq!(insert(&mut storage, &inbox), (1, 0));
+        // The same message in a second folder is stored once and counted as a duplicate
+        assert_eq!(insert(&mut storage, &email("Spam", "<1@example.com>", "Buy now")), (0, 1));
+        // Importing a location again neither inserts nor counts it
+        assert_eq!(insert(&mut storage, &inbox), (0, 0));
+        // A reused Message-ID with another body is a different message
+        assert_eq!(insert(&mut storage, &email("Spam", "<1@example.com>", "Buy later")), (1, 0));
+
+        assert_eq!(top_senders(&mut storage, "Inbox"), [("spam@example.com".to_string(), 1, 1)]);
+        assert_eq!(top_senders(&mut storage, "Spam"), [("spam@example.com".to_string(), 2, 1)]);
+        assert_eq!(top_senders(&mut storage, "Trash"), []);
+    }
+}